@@ -0,0 +1,147 @@
+//! Measures [`State::write`], which every session runs on all incoming data,
+//! and the bare [`Decoder`] it can be compared with.
+//!
+//! Run via `cargo bench --bench decoder`. The `state_write` group is the one to
+//! compare before and after a change of [`State`]. Its numbers include moving
+//! the handled data out of the state, as a session does.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::Read;
+use telnet_server::telnet::{option, Decoder, DecoderEvent, State, StateConfig};
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DO: u8 = 253;
+const NOP: u8 = 241;
+
+/// Size of a single TCP read
+const CHUNK_SIZE: usize = 1024;
+/// Size of the whole input of a benchmark
+const INPUT_SIZE: usize = 64 * 1024;
+
+/// Lines of text, as sent by a client in line mode
+fn text() -> Vec<u8> {
+    b"say Hello there, how are you today?\r\n"
+        .iter()
+        .copied()
+        .cycle()
+        .take(INPUT_SIZE)
+        .collect()
+}
+
+/// Lines of text mixed with commands, escaped IACs and window size changes
+fn mixed() -> Vec<u8> {
+    let mut input = vec![IAC, WILL, option::NAWS];
+    let mut width: u8 = 80;
+
+    while input.len() < INPUT_SIZE {
+        input.extend(b"look at the \xff\xff sign\r\n");
+        input.extend([IAC, NOP]);
+        input.extend([IAC, SB, option::NAWS, 0, width, 0, 24, IAC, SE]);
+        width = if width < 200 { width + 1 } else { 80 };
+    }
+
+    input
+}
+
+/// Feeds `input` into a [`State`] in chunks, reading the handled data
+fn run_state(state: &mut State, input: &[u8], buf: &mut [u8]) {
+    for chunk in input.chunks(CHUNK_SIZE) {
+        black_box(state.write(chunk).expect("Should handle input"));
+
+        while state.read(buf).expect("Should read data") > 0 {
+            black_box(&buf);
+        }
+
+        while let Some(event) = state.poll_event() {
+            black_box(event);
+        }
+    }
+}
+
+/// Feeds `input` into a [`Decoder`] in chunks. If `echo` is set, data is
+/// copied into `echoed` like a server that echoes the input would do.
+fn run_decoder(
+    decoder: &mut Decoder,
+    input: &[u8],
+    replies: &mut Vec<u8>,
+    echoed: &mut Vec<u8>,
+    echo: bool,
+) {
+    for chunk in input.chunks(CHUNK_SIZE) {
+        replies.clear();
+        echoed.clear();
+
+        for event in decoder.decode(chunk, replies) {
+            match event {
+                DecoderEvent::Data(data) if echo => echoed.extend_from_slice(data),
+                _ => {
+                    black_box(event);
+                }
+            }
+        }
+
+        black_box(&replies);
+        black_box(&echoed);
+    }
+}
+
+fn state_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("state_write");
+
+    for (name, input, is_echoing) in [
+        ("text", text(), false),
+        ("mixed", mixed(), false),
+        ("text_echoing", text(), true),
+    ] {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            let mut state = State::new(&StateConfig::default());
+
+            if is_echoing {
+                state
+                    .write(&[IAC, DO, option::ECHO])
+                    .expect("Should enable echo");
+            }
+
+            let mut buf = [0; CHUNK_SIZE];
+            b.iter(|| run_state(&mut state, input, &mut buf));
+        });
+    }
+
+    group.finish();
+}
+
+fn decoder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decoder");
+
+    for (name, input, echo) in [
+        ("text", text(), false),
+        ("mixed", mixed(), false),
+        ("text_echoing", text(), true),
+    ] {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            let mut decoder = Decoder::new(&[option::ECHO], &[option::NAWS]);
+            let mut replies = Vec::with_capacity(CHUNK_SIZE);
+            let mut echoed = Vec::with_capacity(CHUNK_SIZE);
+
+            if echo {
+                decoder
+                    .decode(&[IAC, DO, option::ECHO], &mut replies)
+                    .count();
+            }
+
+            b.iter(|| run_decoder(&mut decoder, input, &mut replies, &mut echoed, echo));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, state_write, decoder);
+criterion_main!(benches);
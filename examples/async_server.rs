@@ -0,0 +1,60 @@
+//! Echo server like `src/bin/main.rs`, but with a Tokio task instead of two
+//! threads per connection.
+//!
+//! Run via `cargo run --example async_server --features tokio`.
+
+use std::io::Result;
+use std::time::Duration;
+use telnet_server::telnet::{option, AsyncSession, State, StateConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+const BIND_ADDRESS: &str = "127.0.0.1:9000";
+/// Users are logged out after not sending anything for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let listener = TcpListener::bind(BIND_ADDRESS).await?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_connection(stream));
+    }
+}
+
+async fn handle_connection(tcp_stream: TcpStream) -> Result<()> {
+    let state = State::new(&StateConfig::default());
+    let mut session = AsyncSession::new(state, tcp_stream);
+
+    // Answers of the client are handled while reading lines, so there's no
+    // need to wait for them here
+    session.request_local(option::ECHO).await?;
+    session.request_local(option::SUPPRESS_GO_AHEAD).await?;
+    session.request_local(option::END_OF_RECORD).await?;
+    session.request_remote(option::NAWS).await?;
+    session.request_remote(option::CHARSET).await?;
+
+    loop {
+        session.write_prompt("> ").await?;
+
+        let incoming = match timeout(IDLE_TIMEOUT, session.read_line()).await {
+            Ok(Ok(Some(i))) => i,
+            Ok(result) => return result.map(|_| ()),
+            Err(_) => {
+                session
+                    .write_str("\nYou have been idle for too long.\n")
+                    .await?;
+                break;
+            }
+        };
+
+        if incoming.trim() == "quit" {
+            break;
+        }
+
+        session.write_str(&format!("You sent: {incoming}")).await?;
+    }
+
+    session.close().await
+}
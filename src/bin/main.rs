@@ -1,18 +1,33 @@
 use std::io::{Error, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
-use telnet_server::read::Read;
-use telnet_server::telnet::{Session, State, StateConfig};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use telnet_server::read::{LineRead, Read};
+use telnet_server::telnet::{option, Session, State, StateConfig};
 
 const BIND_ADDRESS: &str = "127.0.0.1:9000";
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(1);
+/// Users are logged out after not sending anything for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Number of currently connected players, announced via MSSP
+static PLAYERS: AtomicUsize = AtomicUsize::new(0);
 
 fn main() -> std::io::Result<()> {
     let listener = TcpListener::bind(BIND_ADDRESS)?;
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
 
     for stream in listener.incoming() {
         thread::spawn(move || {
             if let Ok(stream) = stream {
-                let _ = handle_connection(stream);
+                PLAYERS.fetch_add(1, Ordering::Relaxed);
+                let _ = handle_connection(stream, start);
+                PLAYERS.fetch_sub(1, Ordering::Relaxed);
             };
         });
     }
@@ -20,9 +35,22 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
-fn handle_connection(tcp_stream: TcpStream) -> Result<(), Error> {
-    // Set up State and Session
-    let state_config = StateConfig::default();
+fn handle_connection(tcp_stream: TcpStream, start: u64) -> Result<(), Error> {
+    // Set up State and Session. Listing sites may ask for the server status.
+    let state_config = StateConfig {
+        mssp: Some(Arc::new(move || {
+            vec![
+                ("NAME".to_string(), "telnet_server".to_string()),
+                ("CODEBASE".to_string(), "telnet_server".to_string()),
+                (
+                    "PLAYERS".to_string(),
+                    PLAYERS.load(Ordering::Relaxed).to_string(),
+                ),
+                ("UPTIME".to_string(), start.to_string()),
+            ]
+        })),
+        ..Default::default()
+    };
     let state = State::new(&state_config);
     let mut session = Session::new(state, tcp_stream)?;
 
@@ -30,12 +58,54 @@ fn handle_connection(tcp_stream: TcpStream) -> Result<(), Error> {
     let session_listen = session.clone();
     let handle = thread::spawn(move || session_listen.listen());
 
+    // Take over echoing the input. Not every client supports this, so it's
+    // fine if the negotiation fails.
+    session.request_local(option::ECHO)?;
+    let _ = session.wait_local(option::ECHO, NEGOTIATION_TIMEOUT);
+
+    // Let the client mark prompts properly
+    session.request_local(option::SUPPRESS_GO_AHEAD)?;
+    session.request_local(option::END_OF_RECORD)?;
+    session.request_local(option::MSSP)?;
+
+    // Ask the client for the size of its terminal window and its type
+    session.request_remote(option::NAWS)?;
+    session.request_remote(option::TERMINAL_TYPE)?;
+    session.request_remote(option::NEW_ENVIRON)?;
+
+    // Compress everything we send, if the client supports it
+    #[cfg(feature = "mccp")]
+    session.request_local(option::COMPRESS2)?;
+
+    // Agree on a character set, so that umlauts and emoji arrive intact
+    session.request_remote(option::CHARSET)?;
+
     loop {
         // Handle incoming TELNET messages:
-        let incoming = session.read_line_waiting()?;
+        if session.write_prompt("> ").is_err() {
+            break;
+        }
+
+        // Stop once the session has been closed, e.g. via LOGOUT, and log out
+        // idle users
+        let incoming = match session.read_line_timeout(IDLE_TIMEOUT) {
+            Ok(LineRead::Line(i)) => i,
+            Ok(LineRead::TimedOut) => {
+                session.write_str("\nYou have been idle for too long.\n")?;
+                session.logout(NEGOTIATION_TIMEOUT)?;
+                break;
+            }
+            _ => break,
+        };
+
+        if incoming.trim() == "quit" {
+            session.logout(NEGOTIATION_TIMEOUT)?;
+            break;
+        }
+
         let answer = format!("You sent: {incoming}");
 
-        if session.write_all(answer.as_bytes()).is_err() {
+        if session.write_str(&answer).is_err() {
             break;
         }
 
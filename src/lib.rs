@@ -1,4 +1,14 @@
+//! Provider for TELNET based services
+//!
+//! The protocol core (see [`telnet::State`]) only requires `alloc`, so it can
+//! be used without the standard library by disabling the default `std`
+//! feature. Everything that deals with actual I/O requires `std`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub(crate) mod iter;
 
+#[cfg(feature = "std")]
 pub mod read;
 pub mod telnet;
@@ -2,12 +2,113 @@
 //! The reason for this module to exist is "missing" - but mandatory -
 //! functionality in the [`std::io::Read`] trait.
 
-use std::io::Error;
+use std::{
+    io::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+/// Result of reading a line that may end without one, see [`Read`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineRead {
+    /// Complete line, ending with `\n`
+    Line(String),
+    /// No complete line has been received in time
+    TimedOut,
+    /// No complete line has been received yet, see [`Read::try_read_line`]
+    Pending,
+    /// The connection has been closed and everything received has been read
+    Disconnected,
+    /// Reading has been cancelled via a [`ShutdownToken`]
+    Cancelled,
+}
+
+/// Callback that wakes up a blocked read, see [`ShutdownToken::on_cancel`]
+pub type CancelWaker = Arc<dyn Fn() + Send + Sync>;
+
+/// Cancels reading lines, e.g. to shut down a server cleanly. Clones share the
+/// same state, so a single token can be handed to every session.
+///
+/// # Examples
+///
+/// ```ignore
+/// use std::thread;
+/// use telnet_server::read::{LineRead, Read, ShutdownToken};
+///
+/// let token = ShutdownToken::new();
+///
+/// let token_session = token.clone();
+/// thread::spawn(move || {
+///     while let Ok(LineRead::Line(line)) = session.read_line_cancellable(&token_session) {
+///         // handle line...
+///     }
+/// });
+///
+/// // Wakes up the reader, which gets LineRead::Cancelled
+/// token.cancel();
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownToken {
+    inner: Arc<TokenInner>,
+}
+
+#[derive(Default)]
+struct TokenInner {
+    is_cancelled: AtomicBool,
+    /// Wakers of the blocked reads. Dead ones are pruned on registration.
+    wakers: Mutex<Vec<Weak<dyn Fn() + Send + Sync>>>,
+}
+
+impl ShutdownToken {
+    /// Creates a new token that hasn't been cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every current and future read that uses this token (or one of
+    /// its clones)
+    pub fn cancel(&self) {
+        self.inner.is_cancelled.store(true, Ordering::SeqCst);
+
+        /* The wakers are called without holding the lock, as they usually
+         * lock the reader's state, which is locked while registering */
+        let wakers: Vec<_> = self
+            .inner
+            .wakers
+            .lock()
+            .expect("Should lock wakers")
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        for waker in wakers {
+            waker();
+        }
+    }
+
+    /// Returns whether [`ShutdownToken::cancel`] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Registers `waker` to be called on [`ShutdownToken::cancel`], as long
+    /// as it's alive. Implementors of [`Read`] use this to wake up a blocked
+    /// read, which then checks [`ShutdownToken::is_cancelled`]. To not miss a
+    /// cancellation, the waker has to be registered before checking.
+    pub fn on_cancel(&self, waker: &CancelWaker) {
+        let mut wakers = self.inner.wakers.lock().expect("Should lock wakers");
+        wakers.retain(|w| w.strong_count() > 0);
+        wakers.push(Arc::downgrade(waker));
+    }
+}
 
 /// Trait for extending [`std::io::Read`] to add "missing" functionality
 pub trait Read {
     /// Reads line to a [`String`], ensuring it is never empty.
-    /// Spins until a `\n` has been found, so that - even if the buffer is empty
+    /// Blocks until a `\n` has been found, so that - even if the buffer is empty
     /// at some point - the result is always a non-empty line.
     ///
     /// # Returns
@@ -33,4 +134,92 @@ pub trait Read {
     /// Ok(())
     /// ```
     fn read_line_waiting(&mut self) -> Result<String, Error>;
+
+    /// Reads a line like [`Read::read_line_waiting`], but gives up after
+    /// `timeout`. An incomplete line is kept for the next read.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum [`Duration`] to wait for a complete line
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(LineRead::Line)` with a line, ending with `\n`
+    /// * `Ok(LineRead::TimedOut)` if no complete line has been received in
+    ///   time
+    /// * `Ok(LineRead::Disconnected)` if the connection has been closed
+    /// * `Err(std::io::Error)` if reading fails
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    /// use telnet_server::read::{LineRead, Read};
+    ///
+    /// match session.read_line_timeout(Duration::from_secs(600))? {
+    ///     LineRead::Line(line) => println!("Received: {line}"),
+    ///     LineRead::TimedOut => session.logout(Duration::from_secs(1))?,
+    ///     _ => {}
+    /// }
+    /// ```
+    fn read_line_timeout(&mut self, timeout: Duration) -> Result<LineRead, Error>;
+
+    /// Reads a line if a complete one has been received already, without
+    /// blocking. An incomplete line is kept for the next read.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(LineRead::Line)` with a line, ending with `\n`
+    /// * `Ok(LineRead::Pending)` if no complete line has been received yet
+    /// * `Ok(LineRead::Disconnected)` if the connection has been closed
+    /// * `Err(std::io::Error)` if reading fails
+    fn try_read_line(&mut self) -> Result<LineRead, Error> {
+        match self.read_line_timeout(Duration::ZERO)? {
+            LineRead::TimedOut => Ok(LineRead::Pending),
+            result => Ok(result),
+        }
+    }
+
+    /// Reads a line like [`Read::read_line_waiting`], but stops once `token`
+    /// has been cancelled. An incomplete line is kept for the next read.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - [`ShutdownToken`] that cancels the read
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(LineRead::Line)` with a line, ending with `\n`
+    /// * `Ok(LineRead::Cancelled)` if `token` has been cancelled
+    /// * `Ok(LineRead::Disconnected)` if the connection has been closed
+    /// * `Err(std::io::Error)` if reading fails
+    fn read_line_cancellable(&mut self, token: &ShutdownToken) -> Result<LineRead, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn wakes_registered_readers_on_cancel() {
+        let token = ShutdownToken::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let counter = calls.clone();
+        let waker: CancelWaker = Arc::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        token.on_cancel(&waker);
+
+        let gone: CancelWaker = Arc::new(|| panic!("Should not be called"));
+        token.on_cancel(&gone);
+        drop(gone);
+
+        assert!(!token.is_cancelled());
+        token.clone().cancel();
+
+        assert!(token.is_cancelled());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,824 @@
+use super::codec::Codec;
+use super::environment::Variable;
+#[cfg(feature = "gmcp")]
+use super::gmcp::GmcpMessage;
+use super::msdp::MsdpValue;
+use super::state::Bytes;
+use super::{option, Event, State, WindowSize};
+use std::{
+    collections::BTreeMap,
+    future::poll_fn,
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+/// Handles the TCP connection for a TELNET service on top of Tokio, like
+/// [`super::Session`] does with threads.
+///
+/// There's no background listener: incoming data is handled while reading
+/// (via [`AsyncSession::read_line`] or [`AsyncRead`]) and while waiting for
+/// an answer of the other side (e.g. via [`AsyncSession::wait_local`]).
+/// Replies are queued and sent on the next read or write, so every future of
+/// this type can be cancelled safely, e.g. by `tokio::time::timeout` or
+/// `tokio::select!`.
+///
+/// Implements [`AsyncRead`] and [`AsyncWrite`] to receive and send messages
+/// from/to the connection.
+///
+/// # Examples
+///
+/// ```ignore
+/// use telnet_server::telnet::{option, AsyncSession, State, StateConfig};
+///
+/// let (tcp_stream, _) = listener.accept().await?;
+/// let mut session = AsyncSession::new(State::new(&StateConfig::default()), tcp_stream);
+///
+/// session.request_local(option::ECHO).await?;
+///
+/// while let Some(line) = session.read_line().await? {
+///     session.write_str(&format!("You sent: {line}")).await?;
+/// }
+/// ```
+pub struct AsyncSession {
+    /// TELNET connection [`State`]
+    state: State,
+    /// The TCP connection
+    tcp_stream: TcpStream,
+    /// Data that has been produced by the state but not sent yet
+    outgoing: Vec<u8>,
+}
+
+impl AsyncSession {
+    /// Creates new [`AsyncSession`] based on given [`TcpStream`] and a fresh
+    /// [`State`].
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A fresh [`State`]
+    /// * `tcp_stream` - [`TcpStream`] for a TELNET based session. Notice that
+    ///   there's no instant check if this is for TELNET or anthother protocol.
+    pub fn new(state: State, tcp_stream: TcpStream) -> Self {
+        Self {
+            state,
+            tcp_stream,
+            outgoing: vec![],
+        }
+    }
+
+    /// Returns the internal [`State`], e.g. to check whether an option has
+    /// been enabled or to get the window size of the client
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Requests the other side to let us enable `option` by sending WILL.
+    /// Does nothing if the option is already enabled or being negotiated.
+    ///
+    /// Notice that this only _starts_ the negotiation. The answer is handled
+    /// while reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub async fn request_local(&mut self, option: u8) -> Result<()> {
+        let data = self.state.enable_local(option);
+        self.send(data).await
+    }
+
+    /// Requests the other side to enable `option` by sending DO. Does nothing
+    /// if the option is already enabled or being negotiated.
+    ///
+    /// Notice that this only _starts_ the negotiation. The answer is handled
+    /// while reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub async fn request_remote(&mut self, option: u8) -> Result<()> {
+        let data = self.state.enable_remote(option);
+        self.send(data).await
+    }
+
+    /// Waits until the negotiation of `option` on our side has finished,
+    /// handling incoming data meanwhile. Lines received while waiting are kept
+    /// for [`AsyncSession::read_line`].
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` whether the option is enabled after the negotiation
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the other
+    ///   side didn't answer in time, of kind [`ErrorKind::NotConnected`] if
+    ///   the session has been closed meanwhile, or any other on TCP errors
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    /// use telnet_server::telnet::option;
+    ///
+    /// session.request_local(option::ECHO).await?;
+    /// if !session.wait_local(option::ECHO, Duration::from_secs(5)).await? {
+    ///     session.write_str("Your password will be visible.\n").await?;
+    /// }
+    /// ```
+    pub async fn wait_local(&mut self, option: u8, timeout: Duration) -> Result<bool> {
+        self.wait_for_answer(timeout, |state| {
+            if state.is_local_pending(option) {
+                None
+            } else {
+                Some(state.is_local_enabled(option))
+            }
+        })
+        .await
+    }
+
+    /// Waits until the negotiation of `option` on the other side has
+    /// finished, like [`AsyncSession::wait_local`]
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` whether the option is enabled after the negotiation
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the other
+    ///   side didn't answer in time, of kind [`ErrorKind::NotConnected`] if
+    ///   the session has been closed meanwhile, or any other on TCP errors
+    pub async fn wait_remote(&mut self, option: u8, timeout: Duration) -> Result<bool> {
+        self.wait_for_answer(timeout, |state| {
+            if state.is_remote_pending(option) {
+                None
+            } else {
+                Some(state.is_remote_enabled(option))
+            }
+        })
+        .await
+    }
+
+    /// Returns the next received [`Event`] that hasn't been polled yet, e.g.
+    /// a [`super::Signal`] sent by the client
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.state.poll_event()
+    }
+
+    /// Returns the last size of the terminal window that has been sent by the
+    /// client, see [`super::Session::window_size`]
+    pub fn window_size(&self) -> Option<WindowSize> {
+        self.state.window_size()
+    }
+
+    /// Returns the [`Codec`] that translates between Unicode text and the
+    /// character set of the client, see [`super::Session::codec`]
+    pub fn codec(&self) -> Arc<dyn Codec> {
+        self.state.codec()
+    }
+
+    /// Sets the [`Codec`] that translates between Unicode text and the
+    /// character set of the client, e.g. after asking the user
+    pub fn set_codec(&mut self, codec: Arc<dyn Codec>) {
+        self.state.set_codec(codec);
+    }
+
+    /// Returns the character set that has been agreed on via CHARSET, if any,
+    /// see [`super::Session::charset`]
+    pub fn charset(&self) -> Option<&str> {
+        self.state.charset()
+    }
+
+    /// Returns every terminal type that the client has sent so far, see
+    /// [`super::Session::terminal_types`]
+    pub fn terminal_types(&self) -> &[String] {
+        self.state.terminal_types()
+    }
+
+    /// Returns every environment variable that the client has sent so far,
+    /// see [`super::Session::environment`]
+    pub fn environment(&self) -> &BTreeMap<String, String> {
+        self.state.environment()
+    }
+
+    /// Requests specific environment variables from the client, see
+    /// [`super::Session::request_environment`]. Does nothing if NEW-ENVIRON
+    /// is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `variables` - [`Variable`]s that should be requested
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub async fn request_environment(&mut self, variables: &[Variable]) -> Result<()> {
+        let data = self.state.request_environment(variables);
+        self.send(data).await
+    }
+
+    /// Requests the view of the client on the enabled options, which is
+    /// announced via [`Event::Status`]. Does nothing if STATUS is disabled on
+    /// the other side.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub async fn request_status(&mut self) -> Result<()> {
+        let data = self.state.request_status();
+        self.send(data).await
+    }
+
+    /// Sends an MSDP variable to the client, see [`super::Session::send_msdp`].
+    /// Does nothing if MSDP is disabled on our side.
+    ///
+    /// # Arguments
+    ///
+    /// * `variable` - Name of the variable, e.g. `HEALTH`
+    /// * `value` - [`MsdpValue`] of the variable
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the variable cannot be sent
+    pub async fn send_msdp(&mut self, variable: &str, value: &MsdpValue) -> Result<()> {
+        let data = self.state.encode_msdp(variable, value);
+        self.send(data).await
+    }
+
+    /// Sends a GMCP message to the client, see [`super::Session::send_gmcp`].
+    /// Does nothing if GMCP is disabled on our side.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the message cannot be sent
+    #[cfg(feature = "gmcp")]
+    pub async fn send_gmcp(&mut self, message: &GmcpMessage) -> Result<()> {
+        let data = self.state.encode_gmcp(message);
+        self.send(data).await
+    }
+
+    /// Writes `text` translated into the character set of the client (see
+    /// [`AsyncSession::codec`]), like [`super::Session::write_str`]. A
+    /// trailing carriage return is only sent on the next write or flush.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text that should be shown to the user
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the text cannot be sent
+    pub async fn write_str(&mut self, text: &str) -> Result<()> {
+        let text = self.state.codec().encode(text);
+        let data = self.state.encode(&text);
+        self.send(Some(data)).await
+    }
+
+    /// Writes a prompt and marks its end with "IAC EOR" or "IAC GA", see
+    /// [`super::Session::write_prompt`]
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - Prompt that should be shown to the user
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the prompt cannot be sent
+    pub async fn write_prompt(&mut self, prompt: &str) -> Result<()> {
+        let prompt = self.state.codec().encode(prompt);
+        let data = self.state.encode_prompt(&prompt);
+        self.send(Some(data)).await
+    }
+
+    /// Measures the round-trip time to the client by sending "DO TIMING-MARK"
+    /// and waiting for its answer, see [`super::Session::ping`]. Incoming data
+    /// is handled meanwhile.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Duration)` round-trip time
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the client
+    ///   didn't answer in time, or any other error if the request cannot be
+    ///   sent
+    pub async fn ping(&mut self, timeout: Duration) -> Result<Duration> {
+        let start = Instant::now();
+
+        let (request, number) = self.state.request_timing_mark();
+        self.send(Some(request)).await?;
+
+        self.wait_for_answer(timeout, |state| {
+            if state.timing_marks_answered() >= number {
+                Some(start.elapsed())
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    /// Logs the user out: announces the logout by sending "WILL LOGOUT",
+    /// waits for the client to acknowledge it and closes the session (see
+    /// [`AsyncSession::close`]). The session is closed even if the client
+    /// doesn't answer in time.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the logout cannot be sent or the session
+    ///   cannot be closed
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    ///
+    /// session.write_str("You have been idle for too long.\n").await?;
+    /// session.logout(Duration::from_secs(1)).await?;
+    /// ```
+    pub async fn logout(&mut self, timeout: Duration) -> Result<()> {
+        self.request_local(option::LOGOUT).await?;
+
+        /* The client may also just go away */
+        match self.wait_local(option::LOGOUT, timeout).await {
+            Err(e) if !matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::NotConnected) => {
+                return Err(e)
+            }
+            _ => {}
+        }
+
+        self.close().await
+    }
+
+    /// Reads the next line, handling incoming data until a complete line has
+    /// been received. An incomplete line is kept if the future is dropped, so
+    /// this can be combined with `tokio::time::timeout`.
+    ///
+    /// Once the client asks to be logged out via LOGOUT or closes the
+    /// connection, the session is closed (see [`AsyncSession::close`]).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` with a line, ending with `\n`
+    /// * `Ok(None)` once the session has been closed and everything received
+    ///   has been read
+    /// * `Err(std::io::Error)` on TCP errors
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    /// use tokio::time::timeout;
+    ///
+    /// match timeout(Duration::from_secs(600), session.read_line()).await {
+    ///     Ok(Ok(Some(line))) => println!("Received: {line}"),
+    ///     Ok(Ok(None)) => println!("Disconnected"),
+    ///     Ok(Err(e)) => return Err(e),
+    ///     Err(_) => session.close().await?,
+    /// }
+    /// ```
+    pub async fn read_line(&mut self) -> Result<Option<String>> {
+        poll_fn(|cx| loop {
+            /* Characters may span several bytes, so the line is decoded as a
+             * whole */
+            if let Some(line) = self.state.read_line() {
+                return Poll::Ready(Ok(Some(self.state.codec().decode(&line))));
+            }
+
+            if self.state.is_closed() {
+                return Poll::Ready(Ok(None));
+            }
+
+            ready!(self.poll_receive(cx))?;
+        })
+        .await
+    }
+
+    /// Closes the session: sends pending output, announces [`Event::Closed`]
+    /// and shuts down the TCP stream, like [`super::Session::close`]. Closing
+    /// an already closed session only sends what's still pending.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the TCP stream cannot be shut down
+    pub async fn close(&mut self) -> Result<()> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_shutdown(cx)).await
+    }
+
+    /// Returns whether the session has been closed
+    pub fn is_closed(&self) -> bool {
+        self.state.is_closed()
+    }
+
+    /// Queues `data` (if any) and sends everything that is queued
+    async fn send(&mut self, data: Option<Bytes>) -> Result<()> {
+        if let Some(data) = data {
+            self.outgoing.extend_from_slice(&data);
+        }
+
+        poll_fn(|cx| {
+            ready!(self.poll_send(cx))?;
+            Pin::new(&mut self.tcp_stream).poll_flush(cx)
+        })
+        .await
+    }
+
+    /// Handles incoming data until `check` returns a result for the [`State`]
+    /// or `timeout` is reached. Fails once the session has been closed, as
+    /// nothing is received anymore.
+    async fn wait_for_answer<T, F>(&mut self, timeout: Duration, check: F) -> Result<T>
+    where
+        F: Fn(&State) -> Option<T>,
+    {
+        let answer = poll_fn(|cx| loop {
+            if let Some(result) = check(&self.state) {
+                return Poll::Ready(Ok(result));
+            }
+
+            if self.state.is_closed() {
+                return Poll::Ready(Err(Error::new(
+                    ErrorKind::NotConnected,
+                    "Session has been closed",
+                )));
+            }
+
+            ready!(self.poll_receive(cx))?;
+        });
+
+        tokio::time::timeout(timeout, answer)
+            .await
+            .unwrap_or_else(|_| {
+                Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "Other side has not answered in time",
+                ))
+            })
+    }
+
+    /// Writes queued data to the TCP stream until the queue is empty
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while !self.outgoing.is_empty() {
+            let written = ready!(Pin::new(&mut self.tcp_stream).poll_write(cx, &self.outgoing))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+
+            self.outgoing.drain(..written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Reads from the TCP stream once and lets the [`State`] handle the data.
+    /// Closes the state once the client has closed the connection or asks to
+    /// be logged out.
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        /* Pending replies are sent first. If the client doesn't take them
+         * right now, reading goes on anyway, so that we don't deadlock with
+         * a client that waits for us to read. */
+        if let Poll::Ready(Err(e)) = self.poll_send(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        let mut buf = [0; 1024];
+        let mut read_buf = ReadBuf::new(&mut buf);
+
+        /* A client that goes away abruptly is treated like one that closes
+         * the connection */
+        match ready!(Pin::new(&mut self.tcp_stream).poll_read(cx, &mut read_buf)) {
+            Err(e)
+                if !matches!(
+                    e.kind(),
+                    ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+                ) =>
+            {
+                return Poll::Ready(Err(e));
+            }
+            _ => {}
+        }
+
+        let tcp_data = read_buf.filled();
+
+        /* Nothing is read once the client has closed the connection */
+        let pending = if tcp_data.is_empty() {
+            self.state.close()
+        } else {
+            let response = self.state.write(tcp_data)?;
+
+            if self.state.is_local_enabled(option::LOGOUT) {
+                self.outgoing.extend(response.unwrap_or_default());
+                self.state.close()
+            } else {
+                response
+            }
+        };
+
+        self.outgoing.extend(pending.unwrap_or_default());
+
+        /* Once closed, nothing is read anymore, so the rest is sent on a
+         * best-effort basis */
+        if let Poll::Ready(Err(e)) = self.poll_send(cx) {
+            if !self.state.is_closed() {
+                return Poll::Ready(Err(e));
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Reads handled, non-command data. Incoming data is handled while reading,
+/// see [`AsyncSession`].
+impl AsyncRead for AsyncSession {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        /* Nothing can be read into a full buffer, so there's no need to wait
+         * for data */
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let read = this.state.read_data(buf.initialize_unfilled());
+
+            /* Nothing is read once the session has been closed */
+            if read > 0 || this.state.is_closed() {
+                buf.advance(read);
+                return Poll::Ready(Ok(()));
+            }
+
+            ready!(this.poll_receive(cx))?;
+        }
+    }
+}
+
+/// Writes application data, encoded for TELNET (see [`State::encode`])
+impl AsyncWrite for AsyncSession {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        /* Data is only queued once earlier data has been sent, so a slow
+         * client slows down the writer instead of filling up the memory */
+        ready!(this.poll_send(cx))?;
+
+        let data = this.state.encode(buf);
+        this.outgoing.extend_from_slice(&data);
+
+        if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(data) = this.state.flush_encoding() {
+            this.outgoing.extend_from_slice(&data);
+        }
+
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.tcp_stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(data) = this.state.close() {
+            this.outgoing.extend_from_slice(&data);
+        }
+
+        /* The client may already be gone, so the output is only sent on a
+         * best-effort basis */
+        if ready!(this.poll_send(cx)).is_err() {
+            this.outgoing.clear();
+        }
+
+        match ready!(Pin::new(&mut this.tcp_stream).poll_shutdown(cx)) {
+            Err(e) if e.kind() != ErrorKind::NotConnected => Poll::Ready(Err(e)),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telnet::{Session, StateConfig};
+    use std::time::Duration;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const IAC: u8 = 255;
+    const SB: u8 = 250;
+    const SE: u8 = 240;
+    const WILL: u8 = 251;
+    const DO: u8 = 253;
+
+    /// Returns an [`AsyncSession`] and the client side of its connection
+    async fn connect() -> (AsyncSession, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let session = AsyncSession::new(State::new(&StateConfig::default()), server);
+        (session, client)
+    }
+
+    #[tokio::test]
+    async fn reads_lines_and_negotiates() {
+        let (mut session, mut client) = connect().await;
+
+        session.request_local(option::ECHO).await.unwrap();
+        let mut request = [0; 3];
+        client.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, [IAC, WILL, option::ECHO]);
+
+        client.write_all(&[IAC, DO, option::ECHO]).await.unwrap();
+        client.write_all(b"hello\r\nwor").await.unwrap();
+
+        assert_eq!(
+            session.read_line().await.unwrap().as_deref(),
+            Some("hello\n")
+        );
+        assert!(session.state().is_local_enabled(option::ECHO));
+
+        /* An incomplete line survives a timeout */
+        let result = tokio::time::timeout(Duration::from_millis(50), session.read_line()).await;
+        assert!(result.is_err());
+
+        client.write_all(b"ld\r\n").await.unwrap();
+        assert_eq!(
+            session.read_line().await.unwrap().as_deref(),
+            Some("world\n")
+        );
+
+        drop(client);
+        assert_eq!(session.read_line().await.unwrap(), None);
+        assert_eq!(session.poll_event(), Some(Event::Closed));
+    }
+
+    #[tokio::test]
+    async fn writes_encoded_data() {
+        let (mut session, mut client) = connect().await;
+
+        session.write_all(b"a\xffb\n").await.unwrap();
+        session.flush().await.unwrap();
+        session.close().await.unwrap();
+
+        let mut received = vec![];
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"a\xff\xffb\r\n");
+    }
+
+    #[tokio::test]
+    async fn handles_input_while_waiting_for_answers() {
+        let (mut session, mut client) = connect().await;
+
+        session.request_local(option::MSDP).await.unwrap();
+        let mut request = [0; 3];
+        client.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, [IAC, WILL, option::MSDP]);
+
+        client.write_all(b"hi\r\n").await.unwrap();
+        client.write_all(&[IAC, DO, option::MSDP]).await.unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert!(session.wait_local(option::MSDP, timeout).await.unwrap());
+
+        session
+            .send_msdp("HP", &MsdpValue::String("1".to_string()))
+            .await
+            .unwrap();
+        let mut variable = [0; 10];
+        client.read_exact(&mut variable).await.unwrap();
+        assert_eq!(
+            variable,
+            [IAC, SB, option::MSDP, 1, b'H', b'P', 2, b'1', IAC, SE]
+        );
+
+        /* Lines received while waiting are kept */
+        assert_eq!(session.read_line().await.unwrap().as_deref(), Some("hi\n"));
+
+        session.request_remote(option::NAWS).await.unwrap();
+        let result = session
+            .wait_remote(option::NAWS, Duration::from_millis(50))
+            .await;
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn pings_and_logs_out() {
+        let (mut session, mut client) = connect().await;
+
+        let answering = tokio::spawn(async move {
+            let mut request = [0; 3];
+            client.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [IAC, DO, option::TIMING_MARK]);
+            client
+                .write_all(&[IAC, WILL, option::TIMING_MARK])
+                .await
+                .unwrap();
+
+            client.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [IAC, WILL, option::LOGOUT]);
+            client.write_all(&[IAC, DO, option::LOGOUT]).await.unwrap();
+
+            let mut rest = vec![];
+            client.read_to_end(&mut rest).await.unwrap();
+        });
+
+        session.ping(Duration::from_secs(5)).await.unwrap();
+        session.logout(Duration::from_secs(5)).await.unwrap();
+
+        assert!(session.is_closed());
+        answering.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_nothing_into_full_buffer() {
+        let (mut session, _client) = connect().await;
+
+        /* Nothing has been received, so this would wait otherwise */
+        let read = tokio::time::timeout(Duration::from_secs(5), session.read(&mut []))
+            .await
+            .expect("Should not wait for data");
+        assert_eq!(read.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn writes_text_like_session() {
+        /* A CR LF split over two writes must stay a CR LF */
+        let texts = ["a\r", "\nb"];
+
+        let (mut session, mut client) = connect().await;
+        for text in texts {
+            session.write_str(text).await.unwrap();
+        }
+        session.close().await.unwrap();
+
+        let mut received = vec![];
+        client.read_to_end(&mut received).await.unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let session = Session::new(State::new(&StateConfig::default()), server).unwrap();
+        for text in texts {
+            session.write_str(text).unwrap();
+        }
+        session.close().unwrap();
+
+        let mut expected = vec![];
+        std::io::Read::read_to_end(&mut client, &mut expected).unwrap();
+
+        assert_eq!(received, expected);
+        assert_eq!(received, b"a\r\nb");
+    }
+}
@@ -0,0 +1,213 @@
+//! CHARSET option, see [RFC-2066](https://www.rfc-editor.org/rfc/rfc2066)
+//!
+//! Lets both sides agree on the character set of the transmitted text. Once
+//! the option has been enabled, we offer every character set we support and
+//! the client picks one of them. Requests of the client are answered with the
+//! first character set of its list that we support.
+
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Subnegotiation command: the sender offers character sets
+const REQUEST: u8 = 1;
+/// Subnegotiation command: the receiver has accepted one of the character sets
+const ACCEPTED: u8 = 2;
+/// Subnegotiation command: the receiver supports none of the character sets
+const REJECTED: u8 = 3;
+/// Subnegotiation command: the sender sends a translation table
+const TTABLE_IS: u8 = 4;
+/// Subnegotiation command: the receiver refuses the translation table
+const TTABLE_REJECTED: u8 = 5;
+
+/// Marker that a REQUEST also offers translation tables. It is followed by a
+/// single version byte.
+const TTABLE_MARKER: &[u8] = b"[TTABLE]";
+
+/// Separator of the character sets we offer
+const SEPARATOR: u8 = b';';
+
+/// Character sets that we support, in order of preference. Each of them has a
+/// built-in [`super::codec::Codec`].
+pub const SUPPORTED_CHARSETS: [&str; 3] = ["UTF-8", "ISO-8859-1", "IBM437"];
+
+/// Server side state of the CHARSET option
+#[derive(Default)]
+pub(crate) struct Charset {
+    /// Whether we've sent a REQUEST that hasn't been answered yet
+    is_requesting: bool,
+    /// Character set both sides have agreed on
+    charset: Option<String>,
+}
+
+impl Charset {
+    /// Has to be called once CHARSET has been enabled on either side
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn enable(&mut self) -> Vec<Vec<u8>> {
+        if self.is_requesting || self.charset.is_some() {
+            /* Enabling the other side doesn't require another request */
+            return vec![];
+        }
+
+        self.is_requesting = true;
+
+        let mut payload = vec![REQUEST];
+
+        for charset in SUPPORTED_CHARSETS {
+            payload.push(SEPARATOR);
+            payload.extend_from_slice(charset.as_bytes());
+        }
+
+        vec![payload]
+    }
+
+    /// Has to be called once CHARSET has been disabled on both sides
+    pub(crate) fn disable(&mut self) {
+        self.is_requesting = false;
+        self.charset = None;
+    }
+
+    /// Handles an incoming CHARSET subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client and whether
+    /// a character set has been agreed on
+    pub(crate) fn handle(&mut self, data: &[u8]) -> (Vec<Vec<u8>>, bool) {
+        match data {
+            [REQUEST, list @ ..] => {
+                if self.is_requesting {
+                    /* Both sides have sent a request at the same time, in
+                     * which case the request of the server wins */
+                    return (vec![vec![REJECTED]], false);
+                }
+
+                match Self::select(list) {
+                    Some(charset) => {
+                        let mut payload = vec![ACCEPTED];
+                        payload.extend_from_slice(charset.as_bytes());
+                        self.charset = Some(charset.to_string());
+                        (vec![payload], true)
+                    }
+                    None => (vec![vec![REJECTED]], false),
+                }
+            }
+            [ACCEPTED, name @ ..] if self.is_requesting => {
+                self.is_requesting = false;
+
+                let name = String::from_utf8_lossy(name);
+                let charset = SUPPORTED_CHARSETS
+                    .iter()
+                    .find(|c| c.eq_ignore_ascii_case(&name));
+
+                match charset {
+                    Some(charset) => {
+                        self.charset = Some(charset.to_string());
+                        (vec![], true)
+                    }
+                    /* The client has accepted something we didn't offer */
+                    None => (vec![], false),
+                }
+            }
+            [REJECTED, ..] => {
+                self.is_requesting = false;
+                (vec![], false)
+            }
+            /* We never offer translation tables */
+            [TTABLE_IS, ..] => (vec![vec![TTABLE_REJECTED]], false),
+            _ => (vec![], false),
+        }
+    }
+
+    /// Returns the character set both sides have agreed on, if any
+    pub(crate) fn charset(&self) -> Option<&str> {
+        self.charset.as_deref()
+    }
+
+    /// Selects the first character set out of a REQUEST list that we support
+    fn select(list: &[u8]) -> Option<&'static str> {
+        let list = match list.strip_prefix(TTABLE_MARKER) {
+            /* Skip the version of the translation table */
+            Some(rest) => rest.get(1..)?,
+            None => list,
+        };
+
+        let (&separator, names) = list.split_first()?;
+
+        names.split(|&b| b == separator).find_map(|name| {
+            let name = String::from_utf8_lossy(name);
+            SUPPORTED_CHARSETS
+                .iter()
+                .find(|c| c.eq_ignore_ascii_case(name.trim()))
+                .copied()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_supported_charsets_once() {
+        let mut charset = Charset::default();
+
+        assert_eq!(
+            charset.enable(),
+            vec![b"\x01;UTF-8;ISO-8859-1;IBM437".to_vec()]
+        );
+        assert!(charset.enable().is_empty());
+
+        assert_eq!(charset.handle(b"\x02UTF-8"), (vec![], true));
+        assert_eq!(charset.charset(), Some("UTF-8"));
+    }
+
+    #[test]
+    fn accepts_supported_charset_of_client() {
+        let mut charset = Charset::default();
+
+        assert_eq!(
+            charset.handle(b"\x01 KOI8-R utf-8 ISO-8859-1"),
+            (vec![b"\x02UTF-8".to_vec()], true)
+        );
+        assert_eq!(charset.charset(), Some("UTF-8"));
+    }
+
+    #[test]
+    fn skips_translation_table_version() {
+        let mut charset = Charset::default();
+
+        let (payloads, _) = charset.handle(b"\x01[TTABLE]\x01;KOI8-R;IBM437");
+        assert_eq!(payloads, vec![b"\x02IBM437".to_vec()]);
+    }
+
+    #[test]
+    fn rejects_unsupported_charsets() {
+        let mut charset = Charset::default();
+
+        assert_eq!(
+            charset.handle(b"\x01;KOI8-R"),
+            (vec![vec![REJECTED]], false)
+        );
+        assert_eq!(charset.charset(), None);
+    }
+
+    #[test]
+    fn rejects_request_while_requesting() {
+        let mut charset = Charset::default();
+        charset.enable();
+
+        assert_eq!(charset.handle(b"\x01;UTF-8"), (vec![vec![REJECTED]], false));
+        assert_eq!(charset.charset(), None);
+    }
+}
@@ -0,0 +1,249 @@
+//! Character set codecs
+//!
+//! A [`Codec`] translates between Unicode text and the bytes of the character
+//! set the client uses. Besides UTF-8, the legacy code pages ISO-8859-1 and
+//! CP437 are supported, the latter being expected by many BBS clients for its
+//! box-drawing characters. Further character sets can be added by implementing
+//! [`Codec`] and passing it via [`super::StateConfig::codec`].
+
+use super::terminal_type::{mtts, MTTS_UTF8};
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+/// Character that is sent for every character the client's character set
+/// cannot represent
+const REPLACEMENT: u8 = b'?';
+
+/// Terminal types of clients that expect CP437
+const CP437_TERMINAL_TYPES: [&str; 4] = ["ANSI-BBS", "PCANSI", "SYNCTERM", "CP437"];
+
+/// Characters of CP437 from 0x80 to 0xFF. Everything below is plain ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Translates between Unicode text and the character set of the client
+///
+/// # Examples
+///
+/// ```rust
+/// use telnet_server::telnet::codec::Codec;
+///
+/// /// Codec for clients that only understand 7-bit ASCII
+/// struct Ascii;
+///
+/// impl Codec for Ascii {
+///     fn name(&self) -> &str {
+///         "US-ASCII"
+///     }
+///
+///     fn encode(&self, text: &str) -> Vec<u8> {
+///         text.chars()
+///             .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+///             .collect()
+///     }
+///
+///     fn decode(&self, data: &[u8]) -> String {
+///         data.iter()
+///             .map(|&b| if b.is_ascii() { b as char } else { '?' })
+///             .collect()
+///     }
+/// }
+///
+/// assert_eq!(Ascii.encode("Grüße"), b"Gr??e");
+/// ```
+pub trait Codec: Send + Sync {
+    /// Returns the name of the character set, preferably as registered at
+    /// [IANA](https://www.iana.org/assignments/character-sets)
+    fn name(&self) -> &str;
+
+    /// Encodes `text` into the character set. Characters that cannot be
+    /// represented should be replaced.
+    fn encode(&self, text: &str) -> Vec<u8>;
+
+    /// Decodes `data` of the character set into Unicode text. Invalid data
+    /// should be replaced.
+    fn decode(&self, data: &[u8]) -> String;
+
+    /// Returns whether `byte` continues a character that started with one of
+    /// the previous bytes. Used to erase whole characters. Single-byte
+    /// character sets don't have to override this.
+    fn is_continuation(&self, _byte: u8) -> bool {
+        false
+    }
+}
+
+/// UTF-8, the default of every [`super::State`]
+pub struct Utf8;
+
+impl Codec for Utf8 {
+    fn name(&self) -> &str {
+        "UTF-8"
+    }
+
+    fn encode(&self, text: &str) -> Vec<u8> {
+        text.as_bytes().to_vec()
+    }
+
+    fn decode(&self, data: &[u8]) -> String {
+        String::from_utf8_lossy(data).into_owned()
+    }
+
+    fn is_continuation(&self, byte: u8) -> bool {
+        byte & 0b1100_0000 == 0b1000_0000
+    }
+}
+
+/// ISO-8859-1 (Latin-1), whose bytes equal the first 256 Unicode code points
+pub struct Latin1;
+
+impl Codec for Latin1 {
+    fn name(&self) -> &str {
+        "ISO-8859-1"
+    }
+
+    fn encode(&self, text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| u8::try_from(c).unwrap_or(REPLACEMENT))
+            .collect()
+    }
+
+    fn decode(&self, data: &[u8]) -> String {
+        data.iter().map(|&b| char::from(b)).collect()
+    }
+}
+
+/// CP437, the code page of the original IBM PC, which is used by BBS clients
+/// for its box-drawing characters
+pub struct Cp437;
+
+impl Codec for Cp437 {
+    fn name(&self) -> &str {
+        "IBM437"
+    }
+
+    fn encode(&self, text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| {
+                if c.is_ascii() {
+                    return c as u8;
+                }
+
+                CP437_HIGH
+                    .iter()
+                    .position(|&high| high == c)
+                    .map(|index| 0x80 + index as u8)
+                    .unwrap_or(REPLACEMENT)
+            })
+            .collect()
+    }
+
+    fn decode(&self, data: &[u8]) -> String {
+        data.iter()
+            .map(|&b| {
+                if b.is_ascii() {
+                    char::from(b)
+                } else {
+                    CP437_HIGH[(b - 0x80) as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the built-in [`Codec`] for given character set `name`, if any.
+/// Common aliases are accepted as well.
+///
+/// # Examples
+///
+/// ```rust
+/// use telnet_server::telnet::codec;
+///
+/// let codec = codec::for_name("cp437").unwrap();
+/// assert_eq!(codec.encode("╔═╗"), [0xC9, 0xCD, 0xBB]);
+/// ```
+pub fn for_name(name: &str) -> Option<Arc<dyn Codec>> {
+    let name = name.trim().to_ascii_uppercase();
+
+    match name.as_str() {
+        "UTF-8" | "UTF8" => Some(Arc::new(Utf8)),
+        "ISO-8859-1" | "ISO_8859-1" | "LATIN1" | "L1" => Some(Arc::new(Latin1)),
+        "IBM437" | "CP437" | "437" => Some(Arc::new(Cp437)),
+        _ => None,
+    }
+}
+
+/// Returns the built-in [`Codec`] that fits the terminal types of the client,
+/// if any can be derived from them
+///
+/// # Arguments
+///
+/// * `terminal_types` - All terminal types the client has sent
+///
+/// # Examples
+///
+/// ```rust
+/// use telnet_server::telnet::codec;
+///
+/// let terminal_types = ["SYNCTERM".to_string(), "ANSI".to_string()];
+/// let codec = codec::for_terminal_types(&terminal_types).unwrap();
+/// assert_eq!(codec.name(), "IBM437");
+/// ```
+pub fn for_terminal_types(terminal_types: &[String]) -> Option<Arc<dyn Codec>> {
+    if mtts(terminal_types).is_some_and(|flags| flags & MTTS_UTF8 != 0) {
+        return Some(Arc::new(Utf8));
+    }
+
+    let is_cp437 = terminal_types.iter().any(|terminal_type| {
+        CP437_TERMINAL_TYPES
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(terminal_type))
+    });
+
+    if is_cp437 {
+        Some(Arc::new(Cp437))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_cp437() {
+        let text = "┌─┐ Grüße ░▒▓";
+        let encoded = Cp437.encode(text);
+
+        assert_eq!(encoded[..3], [0xDA, 0xC4, 0xBF]);
+        assert_eq!(Cp437.decode(&encoded), text);
+    }
+
+    #[test]
+    fn covers_every_cp437_byte() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(Cp437.encode(&Cp437.decode(&data)), data);
+    }
+
+    #[test]
+    fn replaces_unknown_characters() {
+        assert_eq!(Latin1.encode("Grüße 😀"), b"Gr\xfc\xdfe ?");
+        assert_eq!(Cp437.encode("€"), b"?");
+    }
+
+    #[test]
+    fn detects_codec_from_terminal_types() {
+        let utf8 = ["ANSI-BBS".to_string(), "MTTS 5".to_string()];
+        assert_eq!(for_terminal_types(&utf8).unwrap().name(), "UTF-8");
+
+        let unknown = ["XTERM".to_string()];
+        assert!(for_terminal_types(&unknown).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! MCCP2 and MCCP3 compression, see
+//! [MCCP](https://tintin.mudhalla.net/protocols/mccp/)
+//!
+//! Negotiated via the COMPRESS2 (outbound) and COMPRESS3 (inbound) options.
+//! Once started, the data of the affected direction is a single zlib stream
+//! until either side ends it. Every chunk of outbound data is flushed, so that
+//! the client doesn't have to wait for more data to decompress it.
+
+use super::error::Error;
+use alloc::vec::Vec;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// Minimum number of bytes that the output buffer grows by
+const MIN_OUTPUT_GROWTH: usize = 64;
+
+/// Compresses outbound data (MCCP2)
+pub(crate) struct Compressor {
+    compress: Compress,
+}
+
+impl Compressor {
+    /// Creates a new [`Compressor`] that starts a fresh zlib stream
+    pub(crate) fn new() -> Self {
+        Self {
+            compress: Compress::new(Compression::default(), true),
+        }
+    }
+
+    /// Compresses `data` and flushes the stream, so that the result can be
+    /// decompressed right away
+    pub(crate) fn compress(&mut self, data: &[u8]) -> Vec<u8> {
+        self.run(data, FlushCompress::Sync)
+    }
+
+    /// Ends the zlib stream. Data sent afterwards is not compressed anymore.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.run(&[], FlushCompress::Finish)
+    }
+
+    fn run(&mut self, data: &[u8], flush: FlushCompress) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len() + MIN_OUTPUT_GROWTH);
+        let total_in = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - total_in) as usize;
+
+            let status = self
+                .compress
+                .compress_vec(&data[consumed..], &mut output, flush)
+                .expect("Should compress data");
+
+            let consumed = (self.compress.total_in() - total_in) as usize;
+
+            /* Spare capacity means that zlib has written everything it had */
+            if status == Status::StreamEnd
+                || (consumed == data.len() && output.len() < output.capacity())
+            {
+                return output;
+            }
+
+            output.reserve(output.capacity().max(MIN_OUTPUT_GROWTH));
+        }
+    }
+}
+
+/// Decompresses inbound data (MCCP3)
+pub(crate) struct Decompressor {
+    decompress: Decompress,
+}
+
+impl Decompressor {
+    /// Creates a new [`Decompressor`] that expects a fresh zlib stream
+    pub(crate) fn new() -> Self {
+        Self {
+            decompress: Decompress::new(true),
+        }
+    }
+
+    /// Decompresses `data`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<u8>, None))` with the decompressed data
+    /// * `Ok((Vec<u8>, Some(usize)))` with the decompressed data and the number
+    ///   of bytes of `data` that belonged to the stream, if the other side has
+    ///   ended it. Any bytes beyond are not compressed anymore.
+    /// * `Err(Error)` if `data` is not a valid zlib stream
+    pub(crate) fn decompress(&mut self, data: &[u8]) -> Result<(Vec<u8>, Option<usize>), Error> {
+        let mut output = Vec::with_capacity(data.len() * 4 + MIN_OUTPUT_GROWTH);
+        let total_in = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+
+            let status = self
+                .decompress
+                .decompress_vec(&data[consumed..], &mut output, FlushDecompress::None)
+                .map_err(|_| Error::InvalidCompressedData)?;
+
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+
+            if status == Status::StreamEnd {
+                return Ok((output, Some(consumed)));
+            }
+
+            if consumed == data.len() && output.len() < output.capacity() {
+                return Ok((output, None));
+            }
+
+            output.reserve(output.capacity().max(MIN_OUTPUT_GROWTH));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompresses_every_flushed_chunk() {
+        let mut compressor = Compressor::new();
+        let mut decompressor = Decompressor::new();
+
+        for chunk in [&b"Hello"[..], &[b'!'; 10_000], b""] {
+            let compressed = compressor.compress(chunk);
+            assert_eq!(
+                decompressor.decompress(&compressed).unwrap(),
+                (chunk.to_vec(), None)
+            );
+        }
+    }
+
+    #[test]
+    fn detects_end_of_stream() {
+        let mut compressor = Compressor::new();
+        let mut decompressor = Decompressor::new();
+
+        let mut data = compressor.compress(b"compressed");
+        data.extend(compressor.finish());
+        let length = data.len();
+        data.extend(b"plain");
+
+        let (decompressed, end) = decompressor.decompress(&data).unwrap();
+        assert_eq!(decompressed, b"compressed");
+        assert_eq!(end, Some(length));
+    }
+
+    #[test]
+    fn refuses_invalid_data() {
+        let mut decompressor = Decompressor::new();
+        assert!(decompressor.decompress(b"not compressed").is_err());
+    }
+}
@@ -0,0 +1,891 @@
+//! Event-driven TELNET decoder
+//!
+//! Low-level alternative to [`super::State::write`] for servers that handle a
+//! lot of sessions. A [`Decoder`] doesn't allocate while decoding: incoming
+//! data is turned into [`DecoderEvent`]s that borrow from the input, and
+//! replies to option negotiations are written into a buffer of the caller.
+//!
+//! The decoder only handles the TELNET layer. Line editing, echoing, character
+//! sets and the payload of most sub negotiations are up to the application, or
+//! to a [`super::State`], which is built on top of a decoder.
+
+use super::naws::WindowSize;
+use super::negotiation::{OptionTable, Reply, Side};
+use super::option;
+use alloc::vec::Vec;
+
+const IAC: u8 = 255;
+/// "IAC SE"
+pub(crate) const IAC_SUBNEGOTIATION_END: u8 = 240;
+/// "IAC SB"
+pub(crate) const IAC_SUBNEGOTIATION_START: u8 = 250;
+pub(crate) const IAC_WILL: u8 = 251;
+pub(crate) const IAC_WONT: u8 = 252;
+pub(crate) const IAC_DO: u8 = 253;
+pub(crate) const IAC_DONT: u8 = 254;
+
+/// Length of a NAWS payload
+const NAWS_LENGTH: usize = 4;
+
+/// Event that has been decoded from incoming data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecoderEvent<'a> {
+    /// Regular data, with every escaped IAC already un-escaped. Line breaks
+    /// are passed on as they are. Data may be split into several events.
+    Data(&'a [u8]),
+    /// A command that isn't handled by the decoder itself, e.g. 244 for
+    /// "IAC IP" or 246 for "IAC AYT"
+    Command(u8),
+    /// `option` has been enabled on given [`Side`]
+    OptionEnabled(Side, u8),
+    /// `option` has been disabled on given [`Side`]
+    OptionDisabled(Side, u8),
+    /// Part of the un-escaped payload of a sub negotiation of an enabled
+    /// option. A payload may be split into several parts, e.g. if it is split
+    /// over several reads. Its last part is marked as `is_last`.
+    Subnegotiation {
+        /// Code of the option
+        option: u8,
+        /// Part of the payload, without the option code
+        data: &'a [u8],
+        /// Whether this is the last part of the payload
+        is_last: bool,
+    },
+    /// The client has sent a new size of its terminal window via NAWS
+    WindowSize(WindowSize),
+    /// The other side has answered a "DO TIMING-MARK"
+    TimingMark,
+}
+
+/// Current position of the [`Decoder`] within the TELNET data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Regular data
+    Data,
+    /// After an IAC
+    Command,
+    /// After "IAC WILL", "IAC WONT", "IAC DO" or "IAC DONT"
+    Negotiation(u8),
+    /// After "IAC SB", waiting for the option
+    SubNegotiationOption,
+    /// Within the payload of a sub negotiation of given option
+    SubNegotiation(u8),
+    /// After an IAC within a sub negotiation of given option. Either escapes
+    /// an IAC or ends the sub negotiation.
+    SubNegotiationIac(u8),
+}
+
+/// Incremental decoder of incoming TELNET data. Keeps track of the
+/// negotiated options and answers negotiations on its own.
+///
+/// # Examples
+///
+/// ```rust
+/// use telnet_server::telnet::{option, Decoder, DecoderEvent, Side};
+///
+/// let mut decoder = Decoder::new(&[option::ECHO], &[option::NAWS]);
+/// let mut replies = Vec::with_capacity(64);
+///
+/// // "Hi", IAC DO ECHO, "!"
+/// let input = [b'H', b'i', 255, 253, option::ECHO, b'!'];
+/// let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+///
+/// assert_eq!(
+///     events,
+///     [
+///         DecoderEvent::Data(b"Hi"),
+///         DecoderEvent::OptionEnabled(Side::Local, option::ECHO),
+///         DecoderEvent::Data(b"!"),
+///     ]
+/// );
+///
+/// // IAC WILL ECHO has to be written back to the TCP connection
+/// assert_eq!(replies, [255, 251, option::ECHO]);
+/// ```
+pub struct Decoder {
+    mode: Mode,
+    /// Negotiation state of every option
+    options: OptionTable,
+    /// Options that we're willing to perform ourselves
+    supported_local_options: [bool; 256],
+    /// Options that we're willing to let the other side perform
+    supported_remote_options: [bool; 256],
+    /// Whether NAWS sub negotiations are turned into
+    /// [`DecoderEvent::WindowSize`]. Otherwise they're passed on as they are.
+    is_decoding_window_size: bool,
+    /// Payload of the current NAWS sub negotiation
+    window_size: [u8; NAWS_LENGTH],
+    /// Number of bytes of the current NAWS sub negotiation so far
+    window_size_length: usize,
+    /// Number of our "DO TIMING-MARK" that haven't been answered yet
+    timing_marks_pending: u64,
+}
+
+impl Decoder {
+    /// Creates a new [`Decoder`] with every option disabled
+    ///
+    /// # Arguments
+    ///
+    /// * `supported_local_options` - Options that we're willing to perform
+    ///   ourselves, see [`super::option`]
+    /// * `supported_remote_options` - Options that we're willing to let the
+    ///   other side perform
+    pub fn new(supported_local_options: &[u8], supported_remote_options: &[u8]) -> Self {
+        Self {
+            mode: Mode::Data,
+            options: OptionTable::new(),
+            supported_local_options: Self::option_set(supported_local_options),
+            supported_remote_options: Self::option_set(supported_remote_options),
+            is_decoding_window_size: true,
+            window_size: [0; NAWS_LENGTH],
+            window_size_length: 0,
+            timing_marks_pending: 0,
+        }
+    }
+
+    /// Decodes a buffer of incoming TELNET data. Data that belongs together
+    /// may be split over several buffers, as the decoder keeps track of where
+    /// the previous buffer has ended.
+    ///
+    /// The data is decoded while iterating over the returned events. If the
+    /// iterator is dropped early, the rest of `input` is decoded anyway and
+    /// its events are discarded, so the decoder never stops in the middle of
+    /// a command. Replies are still added to `replies`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Incoming TELNET data
+    /// * `replies` - Buffer that replies to negotiations are added to. They
+    ///   have to be written back to the other side.
+    ///
+    /// # Returns
+    ///
+    /// Iterator over the [`DecoderEvent`]s of `input`
+    pub fn decode<'a, 'b>(
+        &'b mut self,
+        input: &'a [u8],
+        replies: &'b mut Vec<u8>,
+    ) -> DecoderEvents<'a, 'b> {
+        DecoderEvents {
+            decoder: self,
+            input,
+            position: 0,
+            replies,
+        }
+    }
+
+    /// Decodes the next [`DecoderEvent`] of `input`, starting at `position`.
+    /// Unlike [`Decoder::decode`], the events don't borrow the decoder, so
+    /// the caller may use it between two events.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Incoming TELNET data
+    /// * `position` - Position of the next byte of `input` to decode, which
+    ///   is moved behind the returned event
+    /// * `replies` - Buffer that replies to negotiations are added to
+    /// * `accept` - Decides whether an option may be enabled on given side
+    ///   when the other side asks for it. If it returns `None`, the supported
+    ///   options of [`Decoder::new`] decide.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(DecoderEvent)` with the next event
+    /// * `None` once `input` has been decoded completely
+    pub(crate) fn next_event<'a>(
+        &mut self,
+        input: &'a [u8],
+        position: &mut usize,
+        replies: &mut Vec<u8>,
+        accept: &mut dyn FnMut(Side, u8) -> Option<bool>,
+    ) -> Option<DecoderEvent<'a>> {
+        while *position < input.len() {
+            if let Some(event) = self.step(input, position, replies, accept) {
+                return Some(event);
+            }
+        }
+
+        None
+    }
+
+    /// Requests to enable `option` on our side by adding WILL to `replies`.
+    /// Does nothing if the option is already enabled or being negotiated.
+    pub fn enable_local(&mut self, option: u8, replies: &mut Vec<u8>) {
+        let reply = self.options.request_enable(Side::Local, option);
+        Self::push_negotiation(replies, Side::Local, reply, option);
+    }
+
+    /// Requests to disable `option` on our side by adding WONT to `replies`.
+    /// Does nothing if the option is already disabled or being negotiated.
+    pub fn disable_local(&mut self, option: u8, replies: &mut Vec<u8>) {
+        let reply = self.options.request_disable(Side::Local, option);
+        Self::push_negotiation(replies, Side::Local, reply, option);
+    }
+
+    /// Requests the other side to enable `option` by adding DO to `replies`.
+    /// Does nothing if the option is already enabled or being negotiated.
+    pub fn enable_remote(&mut self, option: u8, replies: &mut Vec<u8>) {
+        let reply = self.options.request_enable(Side::Remote, option);
+        Self::push_negotiation(replies, Side::Remote, reply, option);
+    }
+
+    /// Requests the other side to disable `option` by adding DONT to
+    /// `replies`. Does nothing if the option is already disabled or being
+    /// negotiated.
+    pub fn disable_remote(&mut self, option: u8, replies: &mut Vec<u8>) {
+        let reply = self.options.request_disable(Side::Remote, option);
+        Self::push_negotiation(replies, Side::Remote, reply, option);
+    }
+
+    /// Requests a timing mark by adding "DO TIMING-MARK" to `replies`. Its
+    /// answer is announced as [`DecoderEvent::TimingMark`].
+    pub fn request_timing_mark(&mut self, replies: &mut Vec<u8>) {
+        self.timing_marks_pending += 1;
+        replies.extend_from_slice(&[IAC, IAC_DO, option::TIMING_MARK]);
+    }
+
+    /// Returns whether `option` is currently enabled on given [`Side`]
+    pub fn is_enabled(&self, side: Side, option: u8) -> bool {
+        self.options.is_enabled(side, option)
+    }
+
+    /// Returns the negotiation state of every option
+    pub(crate) fn options(&self) -> &OptionTable {
+        &self.options
+    }
+
+    /// Sets whether NAWS sub negotiations are turned into
+    /// [`DecoderEvent::WindowSize`] (the default) or passed on as they are
+    pub(crate) fn set_decoding_window_size(&mut self, is_decoding: bool) {
+        self.is_decoding_window_size = is_decoding;
+    }
+
+    /// Adds a sub negotiation for `option` to `output`, escaping every IAC of
+    /// `payload`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, Decoder};
+    ///
+    /// // IAC SB TERMINAL-TYPE SEND IAC SE
+    /// let mut output = vec![];
+    /// Decoder::write_sub_negotiation(option::TERMINAL_TYPE, &[1], &mut output);
+    ///
+    /// assert_eq!(output, [255, 250, option::TERMINAL_TYPE, 1, 255, 240]);
+    /// ```
+    pub fn write_sub_negotiation(option: u8, payload: &[u8], output: &mut Vec<u8>) {
+        output.extend_from_slice(&[IAC, IAC_SUBNEGOTIATION_START, option]);
+
+        for &byte in payload {
+            if byte == IAC {
+                output.push(IAC);
+            }
+
+            output.push(byte);
+        }
+
+        output.extend_from_slice(&[IAC, IAC_SUBNEGOTIATION_END]);
+    }
+
+    /// Decodes the next part of the input
+    ///
+    /// # Returns
+    ///
+    /// The [`DecoderEvent`] of the part, if any
+    fn step<'a>(
+        &mut self,
+        input: &'a [u8],
+        position: &mut usize,
+        replies: &mut Vec<u8>,
+        accept: &mut dyn FnMut(Side, u8) -> Option<bool>,
+    ) -> Option<DecoderEvent<'a>> {
+        let start = *position;
+
+        match self.mode {
+            Mode::Data => {
+                let (data, next) = match find_iac(&input[start..]) {
+                    Some(i) => {
+                        self.mode = Mode::Command;
+                        (&input[start..start + i], start + i + 1)
+                    }
+                    None => (&input[start..], input.len()),
+                };
+
+                *position = next;
+                (!data.is_empty()).then_some(DecoderEvent::Data(data))
+            }
+            Mode::Command => {
+                let command = input[start];
+                *position += 1;
+                self.mode = Mode::Data;
+
+                match command {
+                    /* Escaped IAC, which is a regular data byte */
+                    IAC => Some(DecoderEvent::Data(&input[start..=start])),
+                    IAC_WILL | IAC_WONT | IAC_DO | IAC_DONT => {
+                        self.mode = Mode::Negotiation(command);
+                        None
+                    }
+                    IAC_SUBNEGOTIATION_START => {
+                        self.mode = Mode::SubNegotiationOption;
+                        None
+                    }
+                    /* A stray SE has nothing to end */
+                    IAC_SUBNEGOTIATION_END => None,
+                    _ => Some(DecoderEvent::Command(command)),
+                }
+            }
+            Mode::Negotiation(command) => {
+                *position += 1;
+                self.mode = Mode::Data;
+                self.negotiate(command, input[start], replies, accept)
+            }
+            Mode::SubNegotiationOption => {
+                *position += 1;
+                self.mode = Mode::SubNegotiation(input[start]);
+                self.window_size_length = 0;
+                None
+            }
+            Mode::SubNegotiation(option) => {
+                let i = match find_iac(&input[start..]) {
+                    Some(i) => start + i,
+                    None => {
+                        *position = input.len();
+                        return self.sub_negotiation(option, &input[start..], false);
+                    }
+                };
+
+                match input.get(i + 1) {
+                    Some(&IAC) => {
+                        /* Escaped IAC that is part of the payload */
+                        *position = i + 2;
+                        self.sub_negotiation(option, &input[start..=i], false)
+                    }
+                    Some(&next) => {
+                        *position = i + 1;
+                        self.end_sub_negotiation(option, &input[start..i], next, position)
+                    }
+                    None => {
+                        *position = i + 1;
+                        self.mode = Mode::SubNegotiationIac(option);
+                        self.sub_negotiation(option, &input[start..i], false)
+                    }
+                }
+            }
+            Mode::SubNegotiationIac(option) => match input[start] {
+                IAC => {
+                    *position += 1;
+                    self.mode = Mode::SubNegotiation(option);
+                    self.sub_negotiation(option, &input[start..=start], false)
+                }
+                next => self.end_sub_negotiation(option, &[], next, position),
+            },
+        }
+    }
+
+    /// Ends the current sub negotiation once an IAC that doesn't escape
+    /// another IAC has been received
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option
+    /// * `data` - Last part of the payload
+    /// * `next` - Byte after the IAC, which hasn't been consumed yet
+    /// * `position` - Position of `next` within the input
+    fn end_sub_negotiation<'a>(
+        &mut self,
+        option: u8,
+        data: &'a [u8],
+        next: u8,
+        position: &mut usize,
+    ) -> Option<DecoderEvent<'a>> {
+        if next == IAC_SUBNEGOTIATION_END {
+            *position += 1;
+            self.mode = Mode::Data;
+        } else {
+            /* The other side forgot to end the sub negotiation. Treat it as
+             * ended and go on with the command. */
+            self.mode = Mode::Command;
+        }
+
+        self.sub_negotiation(option, data, true)
+    }
+
+    /// Handles an incoming negotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - WILL, WONT, DO or DONT
+    /// * `option` - Code of the option
+    /// * `replies` - Buffer that the reply is added to, if any
+    /// * `accept` - See [`Decoder::next_event`]
+    ///
+    /// # Returns
+    ///
+    /// The [`DecoderEvent`] if the option has been enabled or disabled
+    fn negotiate<'a>(
+        &mut self,
+        command: u8,
+        option: u8,
+        replies: &mut Vec<u8>,
+        accept: &mut dyn FnMut(Side, u8) -> Option<bool>,
+    ) -> Option<DecoderEvent<'a>> {
+        let side = match command {
+            IAC_WILL | IAC_WONT => Side::Remote,
+            _ => Side::Local,
+        };
+
+        if option == option::TIMING_MARK {
+            return self.negotiate_timing_mark(command, replies);
+        }
+
+        let was_enabled = self.options.is_enabled(side, option);
+
+        let reply = if matches!(command, IAC_WILL | IAC_DO) {
+            let accept = accept(side, option).unwrap_or(match side {
+                Side::Local => self.supported_local_options[option as usize],
+                Side::Remote => self.supported_remote_options[option as usize],
+            });
+
+            self.options.receive_enable(side, option, accept)
+        } else {
+            self.options.receive_disable(side, option)
+        };
+
+        Self::push_negotiation(replies, side, reply, option);
+
+        match (was_enabled, self.options.is_enabled(side, option)) {
+            (false, true) => Some(DecoderEvent::OptionEnabled(side, option)),
+            (true, false) => Some(DecoderEvent::OptionDisabled(side, option)),
+            _ => None,
+        }
+    }
+
+    /// Handles an incoming negotiation of TIMING-MARK, which is never really
+    /// enabled
+    ///
+    /// # Returns
+    ///
+    /// [`DecoderEvent::TimingMark`] if one of our timing marks has been
+    /// answered
+    fn negotiate_timing_mark<'a>(
+        &mut self,
+        command: u8,
+        replies: &mut Vec<u8>,
+    ) -> Option<DecoderEvent<'a>> {
+        match command {
+            IAC_DO => {
+                /* Every request has to be answered. As everything received
+                 * before has already been decoded, the answer ends up after
+                 * the output it refers to. */
+                Self::push_negotiation(
+                    replies,
+                    Side::Local,
+                    Some(Reply::Enable),
+                    option::TIMING_MARK,
+                );
+                None
+            }
+            IAC_WILL | IAC_WONT if self.timing_marks_pending > 0 => {
+                self.timing_marks_pending -= 1;
+                Some(DecoderEvent::TimingMark)
+            }
+            IAC_WILL => {
+                /* Nobody asked, so the other side must not perform it */
+                Self::push_negotiation(
+                    replies,
+                    Side::Remote,
+                    Some(Reply::Disable),
+                    option::TIMING_MARK,
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Handles a part of the payload of a sub negotiation
+    ///
+    /// # Returns
+    ///
+    /// The [`DecoderEvent`] of the part, if any. Sub negotiations of disabled
+    /// options are ignored.
+    fn sub_negotiation<'a>(
+        &mut self,
+        option: u8,
+        data: &'a [u8],
+        is_last: bool,
+    ) -> Option<DecoderEvent<'a>> {
+        if option == option::NAWS
+            && self.is_decoding_window_size
+            && self.options.is_enabled(Side::Remote, option)
+        {
+            for &byte in data {
+                if let Some(b) = self.window_size.get_mut(self.window_size_length) {
+                    *b = byte;
+                }
+
+                self.window_size_length += 1;
+            }
+
+            if !is_last {
+                return None;
+            }
+
+            let length = core::mem::take(&mut self.window_size_length);
+
+            /* Longer payloads are invalid and therefore ignored */
+            return self
+                .window_size
+                .get(..length)
+                .and_then(WindowSize::parse)
+                .map(DecoderEvent::WindowSize);
+        }
+
+        if !self.options.is_enabled(Side::Remote, option)
+            && !self.options.is_enabled(Side::Local, option)
+        {
+            return None;
+        }
+
+        if data.is_empty() && !is_last {
+            return None;
+        }
+
+        Some(DecoderEvent::Subnegotiation {
+            option,
+            data,
+            is_last,
+        })
+    }
+
+    /// Adds the negotiation for `reply` to `replies`, if any
+    fn push_negotiation(replies: &mut Vec<u8>, side: Side, reply: Option<Reply>, option: u8) {
+        let command = match (side, reply) {
+            (_, None) => return,
+            (Side::Local, Some(Reply::Enable)) => IAC_WILL,
+            (Side::Local, Some(Reply::Disable)) => IAC_WONT,
+            (Side::Remote, Some(Reply::Enable)) => IAC_DO,
+            (Side::Remote, Some(Reply::Disable)) => IAC_DONT,
+        };
+
+        replies.extend_from_slice(&[IAC, command, option]);
+    }
+
+    /// Turns a list of options into a lookup table
+    fn option_set(options: &[u8]) -> [bool; 256] {
+        let mut set = [false; 256];
+
+        for &option in options {
+            set[option as usize] = true;
+        }
+
+        set
+    }
+}
+
+/// Iterator over the [`DecoderEvent`]s of a buffer, see [`Decoder::decode`].
+/// Events only borrow from the input, not from the [`Decoder`].
+pub struct DecoderEvents<'a, 'b> {
+    decoder: &'b mut Decoder,
+    input: &'a [u8],
+    /// Position of the next byte of `input` to decode
+    position: usize,
+    replies: &'b mut Vec<u8>,
+}
+
+impl<'a> Iterator for DecoderEvents<'a, '_> {
+    type Item = DecoderEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.decoder
+            .next_event(self.input, &mut self.position, self.replies, &mut |_, _| {
+                None
+            })
+    }
+}
+
+/// Decodes the rest of the input, so that the [`Decoder`] is ready for the next
+/// buffer
+impl Drop for DecoderEvents<'_, '_> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// Returns the position of the first IAC in `data`, if any
+fn find_iac(data: &[u8]) -> Option<usize> {
+    data.iter().position(|&b| b == IAC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes every buffer of `inputs`, collecting the replies
+    fn decode_all<'a>(
+        decoder: &mut Decoder,
+        inputs: &[&'a [u8]],
+        replies: &mut Vec<u8>,
+    ) -> Vec<DecoderEvent<'a>> {
+        let mut events = vec![];
+
+        for input in inputs {
+            events.extend(decoder.decode(input, replies));
+        }
+
+        events
+    }
+
+    #[test]
+    fn passes_data_through_without_copying() {
+        let mut decoder = Decoder::new(&[], &[]);
+        let mut replies = vec![];
+        let input = b"Hello, world!\r\n";
+
+        let events: Vec<_> = decoder.decode(input, &mut replies).collect();
+
+        assert_eq!(events, [DecoderEvent::Data(input)]);
+        assert!(matches!(events[0], DecoderEvent::Data(d) if d.as_ptr() == input.as_ptr()));
+    }
+
+    #[test]
+    fn decodes_rest_of_input_when_dropped_early() {
+        let mut decoder = Decoder::new(&[option::ECHO], &[]);
+        let mut replies = vec![];
+
+        let input = [&b"ab"[..], &[IAC, 244], &[IAC, IAC_DO, option::ECHO], b"cd"].concat();
+        let first = decoder.decode(&input, &mut replies).next();
+        assert_eq!(first, Some(DecoderEvent::Data(b"ab")));
+        assert_eq!(replies, [IAC, IAC_WILL, option::ECHO]);
+
+        let events: Vec<_> = decoder.decode(b"ef", &mut replies).collect();
+        assert_eq!(events, [DecoderEvent::Data(b"ef")]);
+    }
+
+    #[test]
+    fn unescapes_iac_split_over_buffers() {
+        let mut decoder = Decoder::new(&[], &[]);
+        let mut replies = vec![];
+
+        let events = decode_all(&mut decoder, &[b"a\xff", b"\xffb\xff\xffc"], &mut replies);
+
+        assert_eq!(
+            events,
+            [
+                DecoderEvent::Data(b"a"),
+                DecoderEvent::Data(&[IAC]),
+                DecoderEvent::Data(b"b"),
+                DecoderEvent::Data(&[IAC]),
+                DecoderEvent::Data(b"c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn negotiates_options() {
+        let mut decoder = Decoder::new(&[option::ECHO], &[]);
+        let mut replies = vec![];
+
+        let input = [
+            [IAC, IAC_DO, option::ECHO],
+            [IAC, IAC_WILL, option::NAWS],
+            [IAC, IAC_DONT, option::ECHO],
+        ]
+        .concat();
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert_eq!(
+            events,
+            [
+                DecoderEvent::OptionEnabled(Side::Local, option::ECHO),
+                DecoderEvent::OptionDisabled(Side::Local, option::ECHO),
+            ]
+        );
+
+        let expected = [
+            [IAC, IAC_WILL, option::ECHO],
+            [IAC, IAC_DONT, option::NAWS],
+            [IAC, IAC_WONT, option::ECHO],
+        ];
+        assert_eq!(replies, expected.concat());
+    }
+
+    #[test]
+    fn does_not_answer_acknowledgement_of_own_request() {
+        let mut decoder = Decoder::new(&[], &[option::TERMINAL_TYPE]);
+        let mut replies = vec![];
+
+        decoder.enable_remote(option::TERMINAL_TYPE, &mut replies);
+        assert_eq!(replies, [IAC, IAC_DO, option::TERMINAL_TYPE]);
+        replies.clear();
+
+        let input = [IAC, IAC_WILL, option::TERMINAL_TYPE];
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert_eq!(
+            events,
+            [DecoderEvent::OptionEnabled(
+                Side::Remote,
+                option::TERMINAL_TYPE
+            )]
+        );
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn passes_commands_on() {
+        let mut decoder = Decoder::new(&[], &[]);
+        let mut replies = vec![];
+
+        /* IAC IP, IAC AYT and a stray IAC SE */
+        let input = [IAC, 244, b'x', IAC, 246, IAC, IAC_SUBNEGOTIATION_END];
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert_eq!(
+            events,
+            [
+                DecoderEvent::Command(244),
+                DecoderEvent::Data(b"x"),
+                DecoderEvent::Command(246),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_sub_negotiation_over_buffers() {
+        let mut decoder = Decoder::new(&[], &[option::TERMINAL_TYPE]);
+        let mut replies = vec![];
+
+        decode_all(
+            &mut decoder,
+            &[&[IAC, IAC_WILL, option::TERMINAL_TYPE]],
+            &mut replies,
+        );
+
+        let events = decode_all(
+            &mut decoder,
+            &[
+                &[IAC, IAC_SUBNEGOTIATION_START, option::TERMINAL_TYPE],
+                &[0, b'x'],
+                &[b't', IAC],
+                &[IAC, b'm', IAC],
+                &[IAC_SUBNEGOTIATION_END, b'!'],
+            ],
+            &mut replies,
+        );
+
+        let part = |data, is_last| DecoderEvent::Subnegotiation {
+            option: option::TERMINAL_TYPE,
+            data,
+            is_last,
+        };
+
+        assert_eq!(
+            events,
+            [
+                part(&[0, b'x'][..], false),
+                part(b"t", false),
+                part(&[IAC], false),
+                part(b"m", false),
+                part(b"", true),
+                DecoderEvent::Data(b"!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_sub_negotiation_without_se() {
+        let mut decoder = Decoder::new(&[option::CHARSET], &[]);
+        let mut replies = vec![];
+
+        let input = [
+            &[IAC, IAC_DO, option::CHARSET][..],
+            &[IAC, IAC_SUBNEGOTIATION_START, option::CHARSET, 2],
+            &[IAC, 244],
+        ]
+        .concat();
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert_eq!(
+            events,
+            [
+                DecoderEvent::OptionEnabled(Side::Local, option::CHARSET),
+                DecoderEvent::Subnegotiation {
+                    option: option::CHARSET,
+                    data: &[2],
+                    is_last: true,
+                },
+                DecoderEvent::Command(244),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_window_size() {
+        let mut decoder = Decoder::new(&[], &[option::NAWS]);
+        let mut replies = vec![];
+
+        /* A sub negotiation of a disabled option is ignored */
+        let naws = [
+            &[IAC, IAC_SUBNEGOTIATION_START, option::NAWS][..],
+            &[0, 80, 0, IAC, IAC],
+            &[IAC, IAC_SUBNEGOTIATION_END],
+        ]
+        .concat();
+        assert_eq!(decoder.decode(&naws, &mut replies).count(), 0);
+
+        decode_all(
+            &mut decoder,
+            &[&[IAC, IAC_WILL, option::NAWS]],
+            &mut replies,
+        );
+        let events: Vec<_> = decoder.decode(&naws, &mut replies).collect();
+
+        assert_eq!(
+            events,
+            [DecoderEvent::WindowSize(WindowSize {
+                width: 80,
+                height: 255,
+            })]
+        );
+    }
+
+    #[test]
+    fn answers_timing_mark() {
+        let mut decoder = Decoder::new(&[], &[]);
+        let mut replies = vec![];
+
+        decoder.request_timing_mark(&mut replies);
+        assert_eq!(replies, [IAC, IAC_DO, option::TIMING_MARK]);
+        replies.clear();
+
+        let input = [
+            [IAC, IAC_DO, option::TIMING_MARK],
+            [IAC, IAC_WILL, option::TIMING_MARK],
+        ]
+        .concat();
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert_eq!(events, [DecoderEvent::TimingMark]);
+        assert_eq!(replies, [IAC, IAC_WILL, option::TIMING_MARK]);
+        assert!(!decoder.is_enabled(Side::Local, option::TIMING_MARK));
+    }
+
+    #[test]
+    fn refuses_unsolicited_timing_mark() {
+        let mut decoder = Decoder::new(&[], &[]);
+        let mut replies = vec![];
+
+        let input = [
+            [IAC, IAC_WILL, option::TIMING_MARK],
+            [IAC, IAC_WONT, option::TIMING_MARK],
+        ]
+        .concat();
+        let events: Vec<_> = decoder.decode(&input, &mut replies).collect();
+
+        assert!(events.is_empty());
+        assert_eq!(replies, [IAC, IAC_DONT, option::TIMING_MARK]);
+    }
+}
@@ -0,0 +1,236 @@
+//! NEW-ENVIRON option, see [RFC-1572](https://www.rfc-editor.org/rfc/rfc1572)
+//!
+//! Lets the client send its environment variables, e.g. `USER` or `LANG`. Once
+//! the client has enabled the option, all of its variables are requested.
+//! Specific variables can be requested afterwards via [`Variable`].
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+/// Subnegotiation command: the sender tells its variables
+const IS: u8 = 0;
+/// Subnegotiation command: the sender asks for variables
+const SEND: u8 = 1;
+/// Subnegotiation command: the sender tells variables that have changed
+const INFO: u8 = 2;
+
+/// Type code: well-known variable
+const VAR: u8 = 0;
+/// Type code: value of the preceding variable
+const VALUE: u8 = 1;
+/// Type code: the following byte is part of a name or value
+const ESC: u8 = 2;
+/// Type code: user defined variable
+const USERVAR: u8 = 3;
+
+/// Variable that can be requested from the client
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Variable {
+    /// Well-known variable (e.g. `USER`, `DISPLAY`)
+    Var(String),
+    /// User defined variable (e.g. `LANG`)
+    UserVar(String),
+}
+
+/// Server side state of the NEW-ENVIRON option
+#[derive(Default)]
+pub(crate) struct Environment {
+    /// Every variable the client has sent. Well-known and user defined
+    /// variables share the same map.
+    variables: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// Has to be called once the client has enabled NEW-ENVIRON
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn enable(&mut self) -> Vec<Vec<u8>> {
+        /* Without names, every variable of the given types is requested */
+        vec![vec![SEND, VAR, USERVAR]]
+    }
+
+    /// Has to be called once the client has disabled NEW-ENVIRON
+    pub(crate) fn disable(&mut self) {
+        self.variables.clear();
+    }
+
+    /// Builds the payload to request given `variables`
+    pub(crate) fn request(variables: &[Variable]) -> Vec<u8> {
+        let mut payload = vec![SEND];
+
+        for variable in variables {
+            let (kind, name) = match variable {
+                Variable::Var(name) => (VAR, name),
+                Variable::UserVar(name) => (USERVAR, name),
+            };
+
+            payload.push(kind);
+
+            for &byte in name.as_bytes() {
+                if byte <= USERVAR {
+                    payload.push(ESC);
+                }
+                payload.push(byte);
+            }
+        }
+
+        payload
+    }
+
+    /// Handles an incoming NEW-ENVIRON subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// Whether any variable has changed
+    pub(crate) fn handle(&mut self, data: &[u8]) -> bool {
+        let list = match data {
+            [IS | INFO, list @ ..] => list,
+            _ => return false,
+        };
+
+        let mut has_changed = false;
+
+        for (name, value) in Self::parse(list) {
+            let previous = match value {
+                Some(value) => self.variables.insert(name, value.clone()) != Some(value),
+                /* Variable is undefined on the client */
+                None => self.variables.remove(&name).is_some(),
+            };
+
+            has_changed |= previous;
+        }
+
+        has_changed
+    }
+
+    /// Returns every variable the client has sent
+    pub(crate) fn variables(&self) -> &BTreeMap<String, String> {
+        &self.variables
+    }
+
+    /// Parses a list of variables
+    ///
+    /// # Returns
+    ///
+    /// Name and value of every variable. Variables without a value are
+    /// undefined on the client.
+    fn parse(list: &[u8]) -> Vec<(String, Option<String>)> {
+        let mut variables = vec![];
+        let mut name: Option<Vec<u8>> = None;
+        let mut value: Option<Vec<u8>> = None;
+        let mut is_escaped = false;
+
+        for &byte in list {
+            if is_escaped {
+                is_escaped = false;
+            } else {
+                match byte {
+                    ESC => {
+                        is_escaped = true;
+                        continue;
+                    }
+                    VAR | USERVAR => {
+                        if let Some(n) = name.take() {
+                            variables.push((n, value.take()));
+                        }
+                        name = Some(vec![]);
+                        continue;
+                    }
+                    VALUE => {
+                        value = Some(vec![]);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            match (&mut name, &mut value) {
+                (Some(_), Some(v)) => v.push(byte),
+                (Some(n), None) => n.push(byte),
+                /* Data before the first variable is invalid */
+                (None, _) => {}
+            }
+        }
+
+        if let Some(n) = name {
+            variables.push((n, value));
+        }
+
+        variables
+            .into_iter()
+            .map(|(n, v)| {
+                (
+                    String::from_utf8_lossy(&n).into_owned(),
+                    v.map(|v| String::from_utf8_lossy(&v).into_owned()),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_variables() {
+        let mut environment = Environment::default();
+
+        let mut data = vec![IS, VAR];
+        data.extend_from_slice(b"USER");
+        data.push(VALUE);
+        data.extend_from_slice(b"laika");
+        data.push(USERVAR);
+        data.extend_from_slice(b"LANG");
+        data.push(VALUE);
+        data.extend_from_slice(b"de_DE.UTF-8");
+
+        assert!(environment.handle(&data));
+        assert_eq!(environment.variables()["USER"], "laika");
+        assert_eq!(environment.variables()["LANG"], "de_DE.UTF-8");
+
+        /* Nothing has changed */
+        assert!(!environment.handle(&data));
+    }
+
+    #[test]
+    fn handles_escaped_bytes() {
+        let mut environment = Environment::default();
+
+        let data = [IS, USERVAR, b'A', ESC, VALUE, VALUE, b'B', ESC, ESC];
+        environment.handle(&data);
+
+        assert_eq!(environment.variables()["A\u{1}"], "B\u{2}");
+    }
+
+    #[test]
+    fn removes_undefined_variables() {
+        let mut environment = Environment::default();
+
+        environment.handle(&[IS, VAR, b'A', VALUE, b'B', VAR, b'C', VALUE]);
+        assert_eq!(environment.variables()["A"], "B");
+        assert_eq!(environment.variables()["C"], "");
+
+        assert!(environment.handle(&[INFO, VAR, b'A']));
+        assert!(!environment.variables().contains_key("A"));
+    }
+
+    #[test]
+    fn escapes_requested_names() {
+        let payload = Environment::request(&[
+            Variable::Var("USER".to_string()),
+            Variable::UserVar("A\u{0}".to_string()),
+        ]);
+
+        assert_eq!(
+            payload,
+            [SEND, VAR, b'U', b'S', b'E', b'R', USERVAR, b'A', ESC, 0]
+        );
+    }
+}
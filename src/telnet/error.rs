@@ -0,0 +1,58 @@
+//! Errors of the TELNET protocol handling
+
+use core::fmt;
+
+/// Error that occurs while handling incoming TELNET data. Once it occurs, the
+/// state of both sides possibly mismatches, so the session should be ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The other side has sent an IAC followed by an unknown command
+    UnknownCommand(u8),
+    /// The other side has sent compressed data (MCCP3) that isn't valid
+    InvalidCompressedData,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand(command) => write!(f, "Unknown command '{command}'"),
+            Error::InvalidCompressedData => write!(f, "Invalid compressed data"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::UnknownCommand(_) => std::io::ErrorKind::InvalidInput,
+            Error::InvalidCompressedData => std::io::ErrorKind::InvalidData,
+        };
+
+        std::io::Error::new(kind, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_error() {
+        assert_eq!(
+            Error::UnknownCommand(42).to_string(),
+            "Unknown command '42'"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn converts_into_io_error() {
+        let error = std::io::Error::from(Error::InvalidCompressedData);
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Invalid compressed data");
+    }
+}
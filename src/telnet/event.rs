@@ -0,0 +1,54 @@
+//! Events that occur on a TELNET connection besides the regular data, e.g.
+//! signals sent by the client.
+
+#[cfg(feature = "gmcp")]
+use super::gmcp::GmcpMessage;
+use super::msdp::MsdpValue;
+use super::naws::WindowSize;
+use super::status::StatusReport;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// Event that has been received by the [`super::State`]. Can be obtained via
+/// [`super::State::poll_event`] or [`super::Session::poll_event`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The client has sent a signal
+    Signal(Signal),
+    /// The client has sent a new size of its terminal window
+    WindowSize(WindowSize),
+    /// The client has sent all of its terminal types
+    TerminalTypes(Vec<String>),
+    /// The client has sent environment variables that have changed. Contains
+    /// all of its known variables.
+    Environment(BTreeMap<String, String>),
+    /// Both sides have agreed on a character set via CHARSET
+    Charset(String),
+    /// The client has sent an MSDP variable with its value
+    Msdp(String, MsdpValue),
+    /// The client has sent a GMCP message
+    #[cfg(feature = "gmcp")]
+    Gmcp(GmcpMessage),
+    /// The client has sent its view of the enabled options via STATUS
+    Status(StatusReport),
+    /// The session has been closed, e.g. because the client asked to be
+    /// logged out via LOGOUT. Nothing can be sent anymore.
+    Closed,
+}
+
+/// Signal that has been sent by the client, usually because the user pressed a
+/// key combination like Ctrl-C or Ctrl-D.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// Process should be aborted ("IAC ABORT")
+    Abort,
+    /// Output should be discarded ("IAC AO")
+    AbortOutput,
+    /// Break or attention key has been pressed ("IAC BRK")
+    Break,
+    /// End of file has been reached ("IAC EOF")
+    EndOfFile,
+    /// Process should be interrupted ("IAC IP")
+    InterruptProcess,
+    /// Process should be suspended ("IAC SUSP")
+    Suspend,
+}
@@ -0,0 +1,112 @@
+//! GMCP option, see [GMCP](https://tintin.mudhalla.net/protocols/gmcp/).
+//! Requires the `gmcp` feature.
+//!
+//! Lets server and client exchange JSON messages besides the regular text,
+//! e.g. the vitals of a character. Every message has a name consisting of its
+//! package and the message itself (e.g. `Char.Vitals`), optionally followed by
+//! JSON data.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use serde_json::Value;
+
+/// Message that has been sent via GMCP
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GmcpMessage {
+    /// Full name of the message including its package, e.g. `Char.Vitals`
+    pub name: String,
+    /// JSON data of the message, if any
+    pub data: Option<Value>,
+}
+
+impl GmcpMessage {
+    /// Parses an incoming GMCP subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// * `Some(GmcpMessage)` if the payload is valid
+    /// * `None` if the name is missing or the data is not valid JSON
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        let text = core::str::from_utf8(data).ok()?.trim();
+
+        let (name, data) = match text.split_once(char::is_whitespace) {
+            Some((name, data)) => (name, Some(serde_json::from_str(data).ok()?)),
+            None => (text, None),
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            data,
+        })
+    }
+
+    /// Returns the package of the message, e.g. `Char` for `Char.Vitals`
+    pub fn package(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map(|(package, _)| package)
+            .unwrap_or_default()
+    }
+
+    /// Builds the payload to send this message
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut payload = self.name.clone().into_bytes();
+
+        if let Some(data) = &self.data {
+            payload.push(b' ');
+            payload.extend(data.to_string().into_bytes());
+        }
+
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_message_with_data() {
+        let message = GmcpMessage::parse(br#"Char.Vitals { "hp": 100, "maxhp": 120 }"#).unwrap();
+
+        assert_eq!(message.name, "Char.Vitals");
+        assert_eq!(message.package(), "Char");
+        assert_eq!(message.data, Some(json!({ "hp": 100, "maxhp": 120 })));
+    }
+
+    #[test]
+    fn parses_message_without_data() {
+        let message = GmcpMessage::parse(b"Core.Ping").unwrap();
+
+        assert_eq!(message.name, "Core.Ping");
+        assert_eq!(message.data, None);
+    }
+
+    #[test]
+    fn refuses_invalid_json() {
+        assert!(GmcpMessage::parse(b"Char.Vitals { hp").is_none());
+        assert!(GmcpMessage::parse(b"").is_none());
+    }
+
+    #[test]
+    fn encodes_what_it_parses() {
+        let message = GmcpMessage {
+            name: "Room.Info".to_string(),
+            data: Some(json!({ "num": 12345, "exits": { "n": 12344 } })),
+        };
+
+        assert_eq!(GmcpMessage::parse(&message.encode()), Some(message));
+    }
+}
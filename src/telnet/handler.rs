@@ -0,0 +1,90 @@
+//! Extension point for options that aren't handled by this crate
+//!
+//! Implement [`OptionHandler`] and register it via
+//! [`super::StateConfig::option_handlers`] to support additional (e.g. in-house
+//! or MUD specific) options. A registered handler takes precedence over the
+//! built-in handling of its option.
+
+use super::Side;
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+
+/// Handles negotiation and subnegotiation of a single option
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use telnet_server::telnet::{handler::OptionHandler, Side, State, StateConfig};
+///
+/// /// Answers every subnegotiation of option 200 with "PONG"
+/// struct Ping;
+///
+/// impl OptionHandler for Ping {
+///     fn option(&self) -> u8 {
+///         200
+///     }
+///
+///     fn accept(&mut self, side: Side) -> bool {
+///         side == Side::Remote
+///     }
+///
+///     fn sub_negotiation(&mut self, _data: &[u8]) -> Vec<Vec<u8>> {
+///         vec![b"PONG".to_vec()]
+///     }
+/// }
+///
+/// let config = StateConfig {
+///     option_handlers: vec![Arc::new(|| Box::new(Ping))],
+///     ..Default::default()
+/// };
+/// let mut state = State::new(&config);
+///
+/// // IAC WILL 200
+/// let result = state.write(&[255, 251, 200])?;
+/// assert_eq!(result.as_deref(), Some(&[255, 253, 200][..]));
+///
+/// // IAC SB 200 "PING" IAC SE
+/// let result = state.write(&[255, 250, 200, b'P', b'I', b'N', b'G', 255, 240])?;
+/// assert_eq!(result.as_deref(), Some(&[255, 250, 200, b'P', b'O', b'N', b'G', 255, 240][..]));
+///
+/// Ok::<(), telnet_server::telnet::Error>(())
+/// ```
+pub trait OptionHandler: Send {
+    /// Returns the code of the option that this handler is responsible for
+    fn option(&self) -> u8;
+
+    /// Decides whether the option may be enabled on given `side` when the
+    /// other side asks for it (WILL for [`Side::Remote`], DO for
+    /// [`Side::Local`])
+    fn accept(&mut self, side: Side) -> bool;
+
+    /// Called once the option has been enabled on given `side`
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads (without the option code) that should be sent
+    fn enabled(&mut self, _side: Side) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    /// Called once the option has been disabled on given `side`
+    fn disabled(&mut self, _side: Side) {}
+
+    /// Handles a complete subnegotiation of the option. Only called while the
+    /// option is enabled on at least one side.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads (without the option code) that should be sent
+    fn sub_negotiation(&mut self, _data: &[u8]) -> Vec<Vec<u8>> {
+        vec![]
+    }
+}
+
+/// Creates a fresh [`OptionHandler`] for every [`super::State`]
+pub type OptionHandlerFactory = Arc<dyn Fn() -> Box<dyn OptionHandler> + Send + Sync>;
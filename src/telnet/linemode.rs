@@ -0,0 +1,423 @@
+//! LINEMODE option, see [RFC-1184](https://www.rfc-editor.org/rfc/rfc1184)
+//!
+//! Allows the client to edit lines locally and to only send complete lines.
+//! Signals (e.g. Ctrl-C) can be trapped by the client and are then sent as
+//! TELNET commands, which end up as [`super::Event::Signal`].
+
+use alloc::{vec, vec::Vec};
+
+/// Mode bit: client edits lines locally and only sends complete lines
+pub const MODE_EDIT: u8 = 1;
+/// Mode bit: client translates interrupts / signals to TELNET commands
+pub const MODE_TRAPSIG: u8 = 2;
+/// Mode bit: acknowledgement of a mode change
+pub(crate) const MODE_ACK: u8 = 4;
+/// Mode bit: client expands horizontal tabs to spaces
+pub const MODE_SOFT_TAB: u8 = 8;
+/// Mode bit: client echoes non-printable characters literally
+pub const MODE_LIT_ECHO: u8 = 16;
+
+/// Suboption MODE
+pub(crate) const MODE: u8 = 1;
+/// Suboption FORWARDMASK
+const FORWARDMASK: u8 = 2;
+/// Suboption SLC
+const SLC: u8 = 3;
+
+/* These are the TELNET command codes, but used within the subnegotiation
+ * without a preceding IAC. */
+const WILL: u8 = 251;
+const WONT: u8 = 252;
+const DO: u8 = 253;
+const DONT: u8 = 254;
+
+/// Highest known SLC function (SLC_EEOL)
+const SLC_MAX: u8 = 30;
+/// SLC level: function isn't supported
+const SLC_NOSUPPORT: u8 = 0;
+/// SLC level: use the system default
+const SLC_DEFAULT: u8 = 3;
+/// Mask of the SLC level bits
+const SLC_LEVELBITS: u8 = 3;
+/// SLC modifier: acknowledgement of a change
+const SLC_ACK: u8 = 128;
+
+/// SLC function: Synch
+pub const SLC_SYNCH: u8 = 1;
+/// SLC function: Break
+pub const SLC_BRK: u8 = 2;
+/// SLC function: Interrupt Process
+pub const SLC_IP: u8 = 3;
+/// SLC function: Abort Output
+pub const SLC_AO: u8 = 4;
+/// SLC function: Are You There
+pub const SLC_AYT: u8 = 5;
+/// SLC function: End of Record
+pub const SLC_EOR: u8 = 6;
+/// SLC function: Abort
+pub const SLC_ABORT: u8 = 7;
+/// SLC function: End of File
+pub const SLC_EOF: u8 = 8;
+/// SLC function: Suspend
+pub const SLC_SUSP: u8 = 9;
+/// SLC function: Erase Character
+pub const SLC_EC: u8 = 10;
+/// SLC function: Erase Line
+pub const SLC_EL: u8 = 11;
+/// SLC function: Erase Word
+pub const SLC_EW: u8 = 12;
+
+/// Characters that are used for SLC functions set to SLC_DEFAULT, unless
+/// configured via [`LinemodeConfig::characters`]. These are the usual
+/// defaults of Unix terminals.
+const DEFAULT_CHARACTERS: [(u8, u8); 9] = [
+    (SLC_IP, 3),     /* ^C */
+    (SLC_AO, 15),    /* ^O */
+    (SLC_AYT, 20),   /* ^T */
+    (SLC_ABORT, 28), /* ^\ */
+    (SLC_EOF, 4),    /* ^D */
+    (SLC_SUSP, 26),  /* ^Z */
+    (SLC_EC, 127),   /* DEL */
+    (SLC_EL, 21),    /* ^U */
+    (SLC_EW, 23),    /* ^W */
+];
+
+/// Configuration of the LINEMODE option
+#[derive(Clone, Default)]
+pub struct LinemodeConfig {
+    /// Mode that is requested once the client has enabled LINEMODE. Consists
+    /// of [`MODE_EDIT`], [`MODE_TRAPSIG`], [`MODE_SOFT_TAB`] and
+    /// [`MODE_LIT_ECHO`].
+    pub mode: u8,
+    /// If set, the client is asked to forward its buffered data whenever one
+    /// of the characters in this mask is typed. Each byte represents 8 ASCII
+    /// codes, with the high order bit of the first byte being ASCII code 0.
+    /// Must not be longer than 32 bytes.
+    pub forward_mask: Option<Vec<u8>>,
+    /// Characters of SLC functions (e.g. `(SLC_IP, 3)`) that are used when
+    /// the client sets a function to its default. Functions that aren't
+    /// listed here fall back to the usual defaults of Unix terminals.
+    pub characters: Vec<(u8, u8)>,
+}
+
+/// A single entry of the SLC table
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct SlcEntry {
+    /// Level and flags of the function
+    modifiers: u8,
+    /// Character that triggers the function
+    value: u8,
+}
+
+/// Server side state of the LINEMODE option
+pub(crate) struct Linemode {
+    config: LinemodeConfig,
+    /// Currently active mode
+    mode: u8,
+    /// Whether the client has agreed to use our forward mask
+    is_forward_mask_active: bool,
+    /// SLC table, indexed by function. Index 0 is unused.
+    slc: [SlcEntry; SLC_MAX as usize + 1],
+}
+
+impl Linemode {
+    /// Creates a new [`Linemode`] with given configuration. The mode stays at
+    /// its default until [`Linemode::enable`] is called.
+    pub(crate) fn new(config: LinemodeConfig) -> Self {
+        Self {
+            config,
+            mode: 0,
+            is_forward_mask_active: false,
+            slc: [SlcEntry::default(); SLC_MAX as usize + 1],
+        }
+    }
+
+    /// Has to be called once the client has enabled LINEMODE
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn enable(&mut self) -> Vec<Vec<u8>> {
+        let mut payloads = vec![vec![MODE, self.config.mode]];
+
+        if let Some(mask) = &self.config.forward_mask {
+            let mut payload = vec![DO, FORWARDMASK];
+            payload.extend(mask.iter().take(32));
+            payloads.push(payload);
+        }
+
+        payloads
+    }
+
+    /// Has to be called once the client has disabled LINEMODE. Resets
+    /// everything to the defaults of RFC-1184.
+    pub(crate) fn disable(&mut self) {
+        self.mode = 0;
+        self.is_forward_mask_active = false;
+        self.slc = [SlcEntry::default(); SLC_MAX as usize + 1];
+    }
+
+    /// Handles an incoming LINEMODE subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn handle(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        match data {
+            [MODE, mask, ..] => self.handle_mode(*mask).into_iter().collect(),
+            [WILL, FORWARDMASK, ..] => {
+                self.is_forward_mask_active = true;
+                vec![]
+            }
+            [WONT, FORWARDMASK, ..] => {
+                self.is_forward_mask_active = false;
+                vec![]
+            }
+            /* Only the server may send a forward mask */
+            [DO, FORWARDMASK, ..] => vec![vec![WONT, FORWARDMASK]],
+            [DONT, FORWARDMASK, ..] => vec![],
+            [SLC, triplets @ ..] => self.handle_slc(triplets).into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the currently active mode
+    pub(crate) fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    /// Returns whether the client forwards its data based on our forward mask
+    pub(crate) fn is_forward_mask_active(&self) -> bool {
+        self.is_forward_mask_active
+    }
+
+    /// Returns the character that the client uses for given SLC `function`, if
+    /// the function is supported. For functions set to SLC_DEFAULT, this is
+    /// the configured or default character.
+    pub(crate) fn character(&self, function: u8) -> Option<u8> {
+        let entry = self.slc.get(function as usize)?;
+
+        match entry.modifiers & SLC_LEVELBITS {
+            SLC_NOSUPPORT => None,
+            SLC_DEFAULT => self.default_character(function),
+            _ => Some(entry.value),
+        }
+    }
+
+    /// Returns the character of given SLC `function` if the client uses the
+    /// default, see [`LinemodeConfig::characters`]
+    fn default_character(&self, function: u8) -> Option<u8> {
+        self.config
+            .characters
+            .iter()
+            .chain(DEFAULT_CHARACTERS.iter())
+            .find(|(f, _)| *f == function)
+            .map(|&(_, character)| character)
+    }
+
+    /// Handles an incoming MODE
+    ///
+    /// # Returns
+    ///
+    /// The payload of the acknowledgement, if one has to be sent
+    fn handle_mode(&mut self, mask: u8) -> Option<Vec<u8>> {
+        let mode = mask & !MODE_ACK;
+
+        if mask & MODE_ACK != 0 {
+            /* The client has confirmed a mode, which must never be answered */
+            self.mode = mode;
+            return None;
+        }
+
+        if mode == self.mode {
+            return None;
+        }
+
+        /* The client requests another mode, which we're fine with */
+        self.mode = mode;
+        Some(vec![MODE, mode | MODE_ACK])
+    }
+
+    /// Handles an incoming list of SLC triplets
+    ///
+    /// # Returns
+    ///
+    /// The payload of the answer, if one has to be sent
+    fn handle_slc(&mut self, triplets: &[u8]) -> Option<Vec<u8>> {
+        let mut answer = vec![SLC];
+
+        for triplet in triplets.chunks_exact(3) {
+            let (function, modifiers, value) = (triplet[0], triplet[1], triplet[2]);
+
+            if function == 0 {
+                /* The client requests our whole table */
+                answer.extend(self.slc_table());
+                continue;
+            }
+
+            let entry = match self.slc.get_mut(function as usize) {
+                Some(e) => e,
+                None => continue,
+            };
+
+            let new_entry = SlcEntry {
+                modifiers: modifiers & !SLC_ACK,
+                value,
+            };
+
+            if modifiers & SLC_ACK != 0 {
+                *entry = new_entry;
+                continue;
+            }
+
+            if *entry == new_entry {
+                continue;
+            }
+
+            /* We're accepting every character the client proposes */
+            *entry = new_entry;
+            answer.extend([function, new_entry.modifiers | SLC_ACK, value]);
+        }
+
+        if answer.len() > 1 {
+            Some(answer)
+        } else {
+            None
+        }
+    }
+
+    /// Returns triplets for every known SLC function. As we don't have any
+    /// preferences, unknown functions are sent as SLC_DEFAULT so that the
+    /// client uses its own values.
+    fn slc_table(&self) -> Vec<u8> {
+        (1..=SLC_MAX)
+            .flat_map(|function| {
+                let entry = self.slc[function as usize];
+
+                if entry.modifiers & SLC_LEVELBITS == SLC_NOSUPPORT {
+                    [function, SLC_DEFAULT, 0]
+                } else {
+                    [function, entry.modifiers, entry.value]
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLC_VALUE: u8 = 2;
+
+    fn linemode() -> Linemode {
+        Linemode::new(LinemodeConfig {
+            mode: MODE_EDIT | MODE_TRAPSIG,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn requests_configured_mode() {
+        let mut linemode = linemode();
+
+        assert_eq!(
+            linemode.enable(),
+            vec![vec![MODE, MODE_EDIT | MODE_TRAPSIG]]
+        );
+
+        /* Acknowledgements must not be answered */
+        assert!(linemode
+            .handle(&[MODE, MODE_EDIT | MODE_TRAPSIG | MODE_ACK])
+            .is_empty());
+        assert_eq!(linemode.mode(), MODE_EDIT | MODE_TRAPSIG);
+    }
+
+    #[test]
+    fn acknowledges_requested_mode() {
+        let mut linemode = linemode();
+
+        assert_eq!(
+            linemode.handle(&[MODE, MODE_EDIT]),
+            vec![vec![MODE, MODE_EDIT | MODE_ACK]]
+        );
+        assert_eq!(linemode.mode(), MODE_EDIT);
+
+        /* Same mode again, nothing to do */
+        assert!(linemode.handle(&[MODE, MODE_EDIT]).is_empty());
+    }
+
+    #[test]
+    fn acknowledges_slc_changes() {
+        let mut linemode = linemode();
+
+        let answer =
+            linemode.handle(&[SLC, SLC_IP, SLC_VALUE, 3, SLC_EC, SLC_VALUE | SLC_ACK, 127]);
+        assert_eq!(answer, vec![vec![SLC, SLC_IP, SLC_VALUE | SLC_ACK, 3]]);
+
+        assert_eq!(linemode.character(SLC_IP), Some(3));
+        assert_eq!(linemode.character(SLC_EC), Some(127));
+        assert_eq!(linemode.character(SLC_EL), None);
+    }
+
+    #[test]
+    fn falls_back_to_default_characters() {
+        let mut linemode = Linemode::new(LinemodeConfig {
+            mode: MODE_EDIT,
+            characters: vec![(SLC_EC, 8)],
+            ..Default::default()
+        });
+
+        linemode.handle(&[
+            SLC,
+            SLC_EC,
+            SLC_DEFAULT | SLC_ACK,
+            0,
+            SLC_IP,
+            SLC_DEFAULT | SLC_ACK,
+            0,
+            SLC_BRK,
+            SLC_DEFAULT | SLC_ACK,
+            0,
+        ]);
+
+        /* Configured, default and unknown default */
+        assert_eq!(linemode.character(SLC_EC), Some(8));
+        assert_eq!(linemode.character(SLC_IP), Some(3));
+        assert_eq!(linemode.character(SLC_BRK), None);
+    }
+
+    #[test]
+    fn sends_table_on_request() {
+        let mut linemode = linemode();
+        linemode.handle(&[SLC, SLC_IP, SLC_VALUE | SLC_ACK, 3]);
+
+        let answer = linemode.handle(&[SLC, 0, SLC_DEFAULT, 0]);
+        assert_eq!(answer.len(), 1);
+        assert_eq!(answer[0].len(), 1 + SLC_MAX as usize * 3);
+        assert_eq!(answer[0][1..4], [SLC_SYNCH, SLC_DEFAULT, 0]);
+        assert_eq!(answer[0][7..10], [SLC_IP, SLC_VALUE, 3]);
+    }
+
+    #[test]
+    fn tracks_forward_mask() {
+        let mut linemode = Linemode::new(LinemodeConfig {
+            mode: MODE_EDIT,
+            forward_mask: Some(vec![0, 0x04]),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            linemode.enable(),
+            vec![vec![MODE, MODE_EDIT], vec![DO, FORWARDMASK, 0, 0x04]]
+        );
+
+        assert!(linemode.handle(&[WILL, FORWARDMASK]).is_empty());
+        assert!(linemode.is_forward_mask_active());
+    }
+}
@@ -3,12 +3,54 @@
 //! This module contains state and session handling for TCP connections to a
 //! TELNET service.
 //! It has two sub modules:
-//! * [`Session`] handles the TCP connection
+//! * [`Session`] handles the TCP connection (requires the `std` feature)
+//!   * [`AsyncSession`] does the same on top of Tokio (requires the `tokio`
+//!     feature)
 //!   * [`State`] handles the internal (TELNET) state of an existing connection
 //!     * [`StateConfig`] can be used to configure the handling of the [`State`]
 //!       in specific cases.
+//! * [`Decoder`] is a low-level alternative to [`State`] that only decodes the
+//!   TELNET layer, without allocating.
+//!
+//! Codes of the TELNET options known to this crate can be found in [`option`].
+//! Besides regular data, a [`State`] also receives [`Event`]s (e.g. signals).
+#[cfg(feature = "tokio")]
+pub mod async_session;
+pub mod charset;
+pub mod codec;
+#[cfg(feature = "mccp")]
+mod compression;
+pub mod decoder;
+pub mod environment;
+pub mod error;
+pub mod event;
+#[cfg(feature = "gmcp")]
+pub mod gmcp;
+pub mod handler;
+pub mod linemode;
+pub mod msdp;
+pub mod mssp;
+pub mod naws;
+mod negotiation;
+pub mod option;
+#[cfg(feature = "std")]
 pub mod session;
 pub mod state;
+pub mod status;
+pub mod terminal_type;
 
+#[cfg(feature = "tokio")]
+pub use async_session::AsyncSession;
+pub use decoder::{Decoder, DecoderEvent};
+pub use error::Error;
+pub use event::{Event, Signal};
+#[cfg(feature = "gmcp")]
+pub use gmcp::GmcpMessage;
+pub use linemode::LinemodeConfig;
+pub use msdp::MsdpValue;
+pub use naws::WindowSize;
+pub use negotiation::Side;
+#[cfg(feature = "std")]
 pub use session::Session;
-pub use state::{State, StateConfig};
+pub use state::{LineTerminator, State, StateConfig};
+pub use status::StatusReport;
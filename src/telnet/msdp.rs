@@ -0,0 +1,330 @@
+//! MSDP option, see [MSDP](https://tintin.mudhalla.net/protocols/msdp/)
+//!
+//! Lets server and client exchange variables besides the regular text, e.g.
+//! the health of a character. Values are strings, arrays or tables and may be
+//! nested, see [`MsdpValue`].
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+/// Marks the name of a variable
+const VAR: u8 = 1;
+/// Marks the value of a variable
+const VAL: u8 = 2;
+/// Starts a table
+const TABLE_OPEN: u8 = 3;
+/// Ends a table
+const TABLE_CLOSE: u8 = 4;
+/// Starts an array
+const ARRAY_OPEN: u8 = 5;
+/// Ends an array
+const ARRAY_CLOSE: u8 = 6;
+
+/// Maximum nesting depth of tables and arrays. Deeper values are skipped, so
+/// that a client can't exhaust the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Value of an MSDP variable
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MsdpValue {
+    /// Plain value. Numbers are sent as strings as well.
+    String(String),
+    /// List of values
+    Array(Vec<MsdpValue>),
+    /// Named values
+    Table(BTreeMap<String, MsdpValue>),
+}
+
+impl MsdpValue {
+    /// Appends the encoded value to `payload`
+    fn encode(&self, payload: &mut Vec<u8>) {
+        match self {
+            MsdpValue::String(value) => payload.extend(value.bytes().filter(|&b| !is_marker(b))),
+            MsdpValue::Array(values) => {
+                payload.push(ARRAY_OPEN);
+
+                for value in values {
+                    payload.push(VAL);
+                    value.encode(payload);
+                }
+
+                payload.push(ARRAY_CLOSE);
+            }
+            MsdpValue::Table(variables) => {
+                payload.push(TABLE_OPEN);
+
+                for (name, value) in variables {
+                    encode_variable(name, value, payload);
+                }
+
+                payload.push(TABLE_CLOSE);
+            }
+        }
+    }
+}
+
+/// Builds the payload to send `value` as `variable`
+pub(crate) fn encode(variable: &str, value: &MsdpValue) -> Vec<u8> {
+    let mut payload = vec![];
+    encode_variable(variable, value, &mut payload);
+    payload
+}
+
+/// Parses an incoming MSDP subnegotiation
+///
+/// # Arguments
+///
+/// * `data` - Un-escaped payload of the subnegotiation, without the option
+///   code
+///
+/// # Returns
+///
+/// Every variable of the payload, in order
+pub(crate) fn parse(data: &[u8]) -> Vec<(String, MsdpValue)> {
+    Parser {
+        data,
+        position: 0,
+        depth: 0,
+    }
+    .variables(None)
+}
+
+/// Appends VAR `name` VAL `value` to `payload`
+fn encode_variable(name: &str, value: &MsdpValue, payload: &mut Vec<u8>) {
+    payload.push(VAR);
+    payload.extend(name.bytes().filter(|&b| !is_marker(b)));
+    payload.push(VAL);
+    value.encode(payload);
+}
+
+/// Returns whether `byte` is one of the MSDP markers, which must not be part
+/// of names or values
+fn is_marker(byte: u8) -> bool {
+    (VAR..=ARRAY_CLOSE).contains(&byte)
+}
+
+/// Parser of MSDP payloads. Malformed parts are skipped.
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Number of tables and arrays around the current position
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// Parses variables until `end` (or the end of the data) is reached
+    fn variables(&mut self, end: Option<u8>) -> Vec<(String, MsdpValue)> {
+        let mut variables = vec![];
+
+        while let Some(next) = self.peek() {
+            if Some(next) == end {
+                self.position += 1;
+                break;
+            }
+
+            self.position += 1;
+
+            if next != VAR {
+                continue;
+            }
+
+            let name = self.string();
+            let mut values = vec![];
+
+            while self.peek() == Some(VAL) {
+                self.position += 1;
+                values.extend(self.value());
+            }
+
+            /* Several values of the same variable make up an array */
+            let value = match values.len() {
+                0 => MsdpValue::String(String::new()),
+                1 => values.remove(0),
+                _ => MsdpValue::Array(values),
+            };
+
+            variables.push((name, value));
+        }
+
+        variables
+    }
+
+    /// Parses a single value
+    ///
+    /// # Returns
+    ///
+    /// * `Some(MsdpValue)` with the value
+    /// * `None` if the value is nested too deeply and has been skipped
+    fn value(&mut self) -> Option<MsdpValue> {
+        let value = match self.peek() {
+            Some(TABLE_OPEN | ARRAY_OPEN) if self.depth >= MAX_DEPTH => {
+                self.skip_nested();
+                return None;
+            }
+            Some(TABLE_OPEN) => {
+                self.position += 1;
+                self.depth += 1;
+                MsdpValue::Table(self.variables(Some(TABLE_CLOSE)).into_iter().collect())
+            }
+            Some(ARRAY_OPEN) => {
+                self.position += 1;
+                self.depth += 1;
+                MsdpValue::Array(self.array())
+            }
+            _ => return Some(MsdpValue::String(self.string())),
+        };
+
+        self.depth -= 1;
+        Some(value)
+    }
+
+    /// Skips a table or array with everything nested in it. Doesn't recurse,
+    /// regardless of the depth.
+    fn skip_nested(&mut self) {
+        let mut depth = 0;
+
+        while let Some(next) = self.peek() {
+            self.position += 1;
+
+            match next {
+                TABLE_OPEN | ARRAY_OPEN => depth += 1,
+                TABLE_CLOSE | ARRAY_CLOSE => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses the values of an array until its end
+    fn array(&mut self) -> Vec<MsdpValue> {
+        let mut values = vec![];
+
+        while let Some(next) = self.peek() {
+            self.position += 1;
+
+            match next {
+                ARRAY_CLOSE => break,
+                VAL => values.extend(self.value()),
+                _ => {}
+            }
+        }
+
+        values
+    }
+
+    /// Parses a name or value until the next marker
+    fn string(&mut self) -> String {
+        let start = self.position;
+
+        while self.peek().is_some_and(|b| !is_marker(b)) {
+            self.position += 1;
+        }
+
+        String::from_utf8_lossy(&self.data[start..self.position]).into_owned()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> MsdpValue {
+        MsdpValue::String(value.to_string())
+    }
+
+    #[test]
+    fn parses_nested_values() {
+        let data = b"\x01ROOM\x02\x03\x01VNUM\x026008\x01EXITS\x02\x03\x01n\x026011\x04\x04\
+                     \x01LIST\x02\x05\x02a\x02b\x06";
+
+        let exits = BTreeMap::from([("n".to_string(), string("6011"))]);
+        let room = BTreeMap::from([
+            ("VNUM".to_string(), string("6008")),
+            ("EXITS".to_string(), MsdpValue::Table(exits)),
+        ]);
+
+        assert_eq!(
+            parse(data),
+            vec![
+                ("ROOM".to_string(), MsdpValue::Table(room)),
+                (
+                    "LIST".to_string(),
+                    MsdpValue::Array(vec![string("a"), string("b")])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn treats_several_values_as_array() {
+        assert_eq!(
+            parse(b"\x01REPORT\x02HEALTH\x02MANA"),
+            vec![(
+                "REPORT".to_string(),
+                MsdpValue::Array(vec![string("HEALTH"), string("MANA")])
+            )]
+        );
+    }
+
+    #[test]
+    fn encodes_what_it_parses() {
+        let value = MsdpValue::Table(BTreeMap::from([
+            ("HEALTH".to_string(), string("100")),
+            (
+                "AFFECTS".to_string(),
+                MsdpValue::Array(vec![string("blind")]),
+            ),
+        ]));
+
+        let payload = encode("CHARACTER", &value);
+        assert_eq!(parse(&payload), vec![("CHARACTER".to_string(), value)]);
+    }
+
+    #[test]
+    fn skips_malformed_data() {
+        assert_eq!(
+            parse(b"garbage\x01A\x02\x05\x02x"),
+            vec![("A".to_string(), MsdpValue::Array(vec![string("x")]))]
+        );
+    }
+
+    #[test]
+    fn skips_too_deeply_nested_values() {
+        let mut data = b"\x01A\x02".to_vec();
+        for _ in 0..30_000 {
+            data.extend([ARRAY_OPEN, VAL]);
+        }
+        data.extend(b"x\x01B\x02y");
+
+        let variables = parse(&data);
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].0, "A");
+
+        /* Nesting up to the limit is fine */
+        let mut data = b"\x01A\x02".to_vec();
+        data.extend([ARRAY_OPEN, VAL].repeat(MAX_DEPTH));
+        data.extend(b"x");
+        data.extend([ARRAY_CLOSE].repeat(MAX_DEPTH));
+        data.extend(b"\x01B\x02y");
+
+        let variables = parse(&data);
+        assert_eq!(variables[1], ("B".to_string(), string("y")));
+
+        let mut value = &variables[0].1;
+        for _ in 0..MAX_DEPTH {
+            match value {
+                MsdpValue::Array(values) => value = &values[0],
+                _ => panic!("Should be nested array"),
+            }
+        }
+        assert_eq!(value, &string("x"));
+    }
+}
@@ -0,0 +1,76 @@
+//! MSSP option, see [MSSP](https://tintin.mudhalla.net/protocols/mssp/)
+//!
+//! Lets crawlers of MUD listing sites query the status of the server, e.g. its
+//! name and the number of players. The status is taken from an
+//! [`MsspProvider`] every time a client asks for it, so it is always up to
+//! date.
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+/// Marks the name of a variable
+const MSSP_VAR: u8 = 1;
+/// Marks the value of a variable
+const MSSP_VAL: u8 = 2;
+
+/// Returns the current status of the server as variables (e.g. `NAME`,
+/// `PLAYERS`, `UPTIME`, `CODEBASE`) with their values. A variable may occur
+/// several times if it has several values.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use telnet_server::telnet::{mssp::MsspProvider, StateConfig};
+///
+/// let mssp: MsspProvider = Arc::new(|| {
+///     vec![
+///         ("NAME".to_string(), "Seventies MUD".to_string()),
+///         ("PLAYERS".to_string(), "42".to_string()),
+///     ]
+/// });
+///
+/// let config = StateConfig {
+///     mssp: Some(mssp),
+///     ..Default::default()
+/// };
+/// ```
+pub type MsspProvider = Arc<dyn Fn() -> Vec<(String, String)> + Send + Sync>;
+
+/// Builds the payload that announces given `variables`
+pub(crate) fn encode(variables: &[(String, String)]) -> Vec<u8> {
+    let mut payload = vec![];
+
+    for (name, value) in variables {
+        payload.push(MSSP_VAR);
+        payload.extend(name.bytes().filter(|&b| !is_marker(b)));
+        payload.push(MSSP_VAL);
+        payload.extend(value.bytes().filter(|&b| !is_marker(b)));
+    }
+
+    payload
+}
+
+/// Returns whether `byte` marks a name or value, which must not be part of
+/// names or values themselves
+fn is_marker(byte: u8) -> bool {
+    byte == MSSP_VAR || byte == MSSP_VAL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_variables() {
+        let variables = [
+            ("NAME".to_string(), "MUD".to_string()),
+            ("PORT".to_string(), "23".to_string()),
+            ("PORT".to_string(), "4000\x01".to_string()),
+        ];
+
+        assert_eq!(
+            encode(&variables),
+            b"\x01NAME\x02MUD\x01PORT\x0223\x01PORT\x024000"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! NAWS option, see [RFC-1073](https://www.rfc-editor.org/rfc/rfc1073)
+//!
+//! Lets the client tell us the size of its terminal window, both initially and
+//! every time it changes.
+
+/// Size of the terminal window of the client, in characters. A value of `0`
+/// means that the client doesn't know the size in this dimension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WindowSize {
+    /// Number of columns
+    pub width: u16,
+    /// Number of rows
+    pub height: u16,
+}
+
+impl WindowSize {
+    /// Parses the payload of a NAWS subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// * `Some(WindowSize)` if `data` is valid
+    /// * `None` if `data` doesn't consist of exactly 4 bytes
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        match data {
+            [w1, w0, h1, h0] => Some(Self {
+                width: u16::from_be_bytes([*w1, *w0]),
+                height: u16::from_be_bytes([*h1, *h0]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_window_size() {
+        assert_eq!(
+            WindowSize::parse(&[0, 80, 1, 0]),
+            Some(WindowSize {
+                width: 80,
+                height: 256
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_length() {
+        assert_eq!(WindowSize::parse(&[0, 80, 0]), None);
+        assert_eq!(WindowSize::parse(&[0, 80, 0, 24, 0]), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! Option negotiation based on the "Q method" of
+//! [RFC-1143](https://www.rfc-editor.org/rfc/rfc1143).
+//!
+//! Every option is tracked twice: once for our (local) side and once for the
+//! other (remote) side. Tracking pending requests prevents negotiation loops
+//! and ensures that we only acknowledge actual state changes.
+
+use alloc::vec::Vec;
+
+/// Side of the connection that an option is enabled on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    /// Option is performed by us (WILL, WONT)
+    Local,
+    /// Option is performed by the other side (DO, DONT)
+    Remote,
+}
+
+/// Reply that has to be sent to the other side. If this is a WILL / WONT or a
+/// DO / DONT depends on the [`Side`] of the option.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Reply {
+    /// WILL or DO
+    Enable,
+    /// WONT or DONT
+    Disable,
+}
+
+/// State of an option on one side, as described in RFC-1143
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+enum QState {
+    /// Option is disabled
+    #[default]
+    No,
+    /// Option is enabled
+    Yes,
+    /// We've requested to disable the option and wait for an answer
+    WantNo,
+    /// We've requested to enable the option and wait for an answer
+    WantYes,
+}
+
+/// Queue bit of an option, as described in RFC-1143
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+enum Queue {
+    /// No request is queued
+    #[default]
+    Empty,
+    /// The opposite of the current request is queued
+    Opposite,
+}
+
+/// Negotiation state of a single option on a single side
+#[derive(Clone, Copy, Default, Debug)]
+struct OptionState {
+    state: QState,
+    queue: Queue,
+}
+
+impl OptionState {
+    /// Handles an incoming WILL / DO for this option
+    ///
+    /// # Arguments
+    ///
+    /// * `accept` - Whether the option may be enabled at all
+    fn receive_enable(&mut self, accept: bool) -> Option<Reply> {
+        match (self.state, self.queue) {
+            (QState::No, _) => {
+                if accept {
+                    self.state = QState::Yes;
+                    Some(Reply::Enable)
+                } else {
+                    Some(Reply::Disable)
+                }
+            }
+            (QState::Yes, _) => None,
+            (QState::WantNo, Queue::Empty) => {
+                /* Our disable request has been answered with an enable. */
+                self.state = QState::No;
+                None
+            }
+            (QState::WantNo, Queue::Opposite) => {
+                self.state = QState::Yes;
+                self.queue = Queue::Empty;
+                None
+            }
+            (QState::WantYes, Queue::Empty) => {
+                self.state = QState::Yes;
+                None
+            }
+            (QState::WantYes, Queue::Opposite) => {
+                self.state = QState::WantNo;
+                self.queue = Queue::Empty;
+                Some(Reply::Disable)
+            }
+        }
+    }
+
+    /// Handles an incoming WONT / DONT for this option
+    fn receive_disable(&mut self) -> Option<Reply> {
+        match (self.state, self.queue) {
+            (QState::No, _) => None,
+            (QState::Yes, _) => {
+                self.state = QState::No;
+                Some(Reply::Disable)
+            }
+            (QState::WantNo, Queue::Empty) => {
+                self.state = QState::No;
+                None
+            }
+            (QState::WantNo, Queue::Opposite) => {
+                self.state = QState::WantYes;
+                self.queue = Queue::Empty;
+                Some(Reply::Enable)
+            }
+            (QState::WantYes, _) => {
+                self.state = QState::No;
+                self.queue = Queue::Empty;
+                None
+            }
+        }
+    }
+
+    /// Requests to enable this option. Requests that are redundant (e.g.
+    /// because the option is already enabled) are ignored.
+    fn request_enable(&mut self) -> Option<Reply> {
+        match (self.state, self.queue) {
+            (QState::No, _) => {
+                self.state = QState::WantYes;
+                Some(Reply::Enable)
+            }
+            (QState::WantNo, Queue::Empty) => {
+                self.queue = Queue::Opposite;
+                None
+            }
+            (QState::WantYes, Queue::Opposite) => {
+                self.queue = Queue::Empty;
+                None
+            }
+            (QState::Yes, _)
+            | (QState::WantNo, Queue::Opposite)
+            | (QState::WantYes, Queue::Empty) => None,
+        }
+    }
+
+    /// Requests to disable this option. Requests that are redundant (e.g.
+    /// because the option is already disabled) are ignored.
+    fn request_disable(&mut self) -> Option<Reply> {
+        match (self.state, self.queue) {
+            (QState::Yes, _) => {
+                self.state = QState::WantNo;
+                Some(Reply::Disable)
+            }
+            (QState::WantNo, Queue::Opposite) => {
+                self.queue = Queue::Empty;
+                None
+            }
+            (QState::WantYes, Queue::Empty) => {
+                self.queue = Queue::Opposite;
+                None
+            }
+            (QState::No, _)
+            | (QState::WantNo, Queue::Empty)
+            | (QState::WantYes, Queue::Opposite) => None,
+        }
+    }
+}
+
+/// Table holding the negotiation state of every option for both sides
+pub(crate) struct OptionTable {
+    /// Options performed by us
+    local: [OptionState; 256],
+    /// Options performed by the other side
+    remote: [OptionState; 256],
+}
+
+impl OptionTable {
+    /// Creates a new [`OptionTable`] with every option disabled
+    pub(crate) fn new() -> Self {
+        Self {
+            local: [OptionState::default(); 256],
+            remote: [OptionState::default(); 256],
+        }
+    }
+
+    /// Handles an incoming WILL (remote) or DO (local) for `option`
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - [`Side`] that the option should be enabled on
+    /// * `option` - Code of the option
+    /// * `accept` - Whether we're willing to enable the option at all
+    ///
+    /// # Returns
+    ///
+    /// The [`Reply`] that has to be sent back, if any
+    pub(crate) fn receive_enable(&mut self, side: Side, option: u8, accept: bool) -> Option<Reply> {
+        self.get_mut(side, option).receive_enable(accept)
+    }
+
+    /// Handles an incoming WONT (remote) or DONT (local) for `option`
+    ///
+    /// # Returns
+    ///
+    /// The [`Reply`] that has to be sent back, if any
+    pub(crate) fn receive_disable(&mut self, side: Side, option: u8) -> Option<Reply> {
+        self.get_mut(side, option).receive_disable()
+    }
+
+    /// Requests to enable `option` on given `side`
+    ///
+    /// # Returns
+    ///
+    /// The [`Reply`] that has to be sent, if any
+    pub(crate) fn request_enable(&mut self, side: Side, option: u8) -> Option<Reply> {
+        self.get_mut(side, option).request_enable()
+    }
+
+    /// Requests to disable `option` on given `side`
+    ///
+    /// # Returns
+    ///
+    /// The [`Reply`] that has to be sent, if any
+    pub(crate) fn request_disable(&mut self, side: Side, option: u8) -> Option<Reply> {
+        self.get_mut(side, option).request_disable()
+    }
+
+    /// Returns whether `option` is currently enabled on given `side`
+    pub(crate) fn is_enabled(&self, side: Side, option: u8) -> bool {
+        self.get(side, option).state == QState::Yes
+    }
+
+    /// Returns whether we're waiting for an answer regarding `option` on given
+    /// `side`
+    pub(crate) fn is_pending(&self, side: Side, option: u8) -> bool {
+        matches!(
+            self.get(side, option).state,
+            QState::WantNo | QState::WantYes
+        )
+    }
+
+    /// Returns every option that is currently enabled on given `side`, in
+    /// ascending order
+    pub(crate) fn enabled(&self, side: Side) -> Vec<u8> {
+        (0..=u8::MAX)
+            .filter(|&option| self.is_enabled(side, option))
+            .collect()
+    }
+
+    fn get(&self, side: Side, option: u8) -> &OptionState {
+        match side {
+            Side::Local => &self.local[option as usize],
+            Side::Remote => &self.remote[option as usize],
+        }
+    }
+
+    fn get_mut(&mut self, side: Side, option: u8) -> &mut OptionState {
+        match side {
+            Side::Local => &mut self.local[option as usize],
+            Side::Remote => &mut self.remote[option as usize],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTION: u8 = 1;
+
+    #[test]
+    fn accepts_enable_once() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(
+            table.receive_enable(Side::Local, OPTION, true),
+            Some(Reply::Enable)
+        );
+        assert!(table.is_enabled(Side::Local, OPTION));
+
+        /* Already enabled, so there must not be another answer */
+        assert_eq!(table.receive_enable(Side::Local, OPTION, true), None);
+    }
+
+    #[test]
+    fn refuses_unsupported_option() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(
+            table.receive_enable(Side::Remote, OPTION, false),
+            Some(Reply::Disable)
+        );
+        assert!(!table.is_enabled(Side::Remote, OPTION));
+    }
+
+    #[test]
+    fn ignores_disable_of_disabled_option() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(table.receive_disable(Side::Local, OPTION), None);
+        assert!(!table.is_enabled(Side::Local, OPTION));
+    }
+
+    #[test]
+    fn acknowledges_disable_of_enabled_option() {
+        let mut table = OptionTable::new();
+
+        table.receive_enable(Side::Remote, OPTION, true);
+        assert_eq!(
+            table.receive_disable(Side::Remote, OPTION),
+            Some(Reply::Disable)
+        );
+        assert!(!table.is_enabled(Side::Remote, OPTION));
+    }
+
+    #[test]
+    fn does_not_answer_acknowledgement_of_own_request() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(
+            table.request_enable(Side::Local, OPTION),
+            Some(Reply::Enable)
+        );
+        assert!(table.is_pending(Side::Local, OPTION));
+
+        /* Answering the acknowledgement would result in a negotiation loop */
+        assert_eq!(table.receive_enable(Side::Local, OPTION, true), None);
+        assert!(table.is_enabled(Side::Local, OPTION));
+        assert!(!table.is_pending(Side::Local, OPTION));
+    }
+
+    #[test]
+    fn lists_enabled_options() {
+        let mut table = OptionTable::new();
+
+        table.receive_enable(Side::Local, 3, true);
+        table.receive_enable(Side::Local, OPTION, true);
+        table.request_enable(Side::Local, 24);
+        table.receive_enable(Side::Remote, u8::MAX, true);
+
+        /* Pending requests are not enabled yet */
+        assert_eq!(table.enabled(Side::Local), [OPTION, 3]);
+        assert_eq!(table.enabled(Side::Remote), [u8::MAX]);
+    }
+
+    #[test]
+    fn handles_refused_request() {
+        let mut table = OptionTable::new();
+
+        table.request_enable(Side::Remote, OPTION);
+        assert_eq!(table.receive_disable(Side::Remote, OPTION), None);
+        assert!(!table.is_enabled(Side::Remote, OPTION));
+        assert!(!table.is_pending(Side::Remote, OPTION));
+    }
+
+    #[test]
+    fn handles_queued_opposite_request() {
+        let mut table = OptionTable::new();
+
+        table.request_enable(Side::Local, OPTION);
+        /* Changed our mind while waiting for the answer */
+        assert_eq!(table.request_disable(Side::Local, OPTION), None);
+
+        assert_eq!(
+            table.receive_enable(Side::Local, OPTION, true),
+            Some(Reply::Disable)
+        );
+        assert!(table.is_pending(Side::Local, OPTION));
+
+        assert_eq!(table.receive_disable(Side::Local, OPTION), None);
+        assert!(!table.is_enabled(Side::Local, OPTION));
+        assert!(!table.is_pending(Side::Local, OPTION));
+    }
+}
@@ -0,0 +1,60 @@
+//! TELNET option codes
+//!
+//! Contains the codes of all TELNET options that are known to this crate. They
+//! can be used to request options via a [`super::Session`].
+
+/// TRANSMIT-BINARY option, see [RFC-856](https://www.rfc-editor.org/rfc/rfc856)
+pub const BINARY: u8 = 0;
+
+/// ECHO option, see [RFC-857](https://www.rfc-editor.org/rfc/rfc857)
+pub const ECHO: u8 = 1;
+
+/// SUPPRESS-GO-AHEAD option, see [RFC-858](https://www.rfc-editor.org/rfc/rfc858)
+pub const SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// STATUS option, see [RFC-859](https://www.rfc-editor.org/rfc/rfc859)
+pub const STATUS: u8 = 5;
+
+/// TIMING-MARK option, see [RFC-860](https://www.rfc-editor.org/rfc/rfc860)
+pub const TIMING_MARK: u8 = 6;
+
+/// LOGOUT option, see [RFC-727](https://www.rfc-editor.org/rfc/rfc727)
+pub const LOGOUT: u8 = 18;
+
+/// TERMINAL-TYPE option, see [RFC-1091](https://www.rfc-editor.org/rfc/rfc1091)
+pub const TERMINAL_TYPE: u8 = 24;
+
+/// END-OF-RECORD option, see [RFC-885](https://www.rfc-editor.org/rfc/rfc885)
+pub const END_OF_RECORD: u8 = 25;
+
+/// NAWS option, see [RFC-1073](https://www.rfc-editor.org/rfc/rfc1073)
+pub const NAWS: u8 = 31;
+
+/// LINEMODE option, see [RFC-1184](https://www.rfc-editor.org/rfc/rfc1184)
+pub const LINEMODE: u8 = 34;
+
+/// NEW-ENVIRON option, see [RFC-1572](https://www.rfc-editor.org/rfc/rfc1572)
+pub const NEW_ENVIRON: u8 = 39;
+
+/// CHARSET option, see [RFC-2066](https://www.rfc-editor.org/rfc/rfc2066)
+pub const CHARSET: u8 = 42;
+
+/// MSDP option, see [MSDP](https://tintin.mudhalla.net/protocols/msdp/)
+pub const MSDP: u8 = 69;
+
+/// MSSP option, see [MSSP](https://tintin.mudhalla.net/protocols/mssp/)
+pub const MSSP: u8 = 70;
+
+/// COMPRESS2 option (MCCP2), see
+/// [MCCP](https://tintin.mudhalla.net/protocols/mccp/). Requires the `mccp`
+/// feature.
+pub const COMPRESS2: u8 = 86;
+
+/// COMPRESS3 option (MCCP3), see
+/// [MCCP](https://tintin.mudhalla.net/protocols/mccp/). Requires the `mccp`
+/// feature.
+pub const COMPRESS3: u8 = 87;
+
+/// GMCP option, see [GMCP](https://tintin.mudhalla.net/protocols/gmcp/).
+/// Requires the `gmcp` feature.
+pub const GMCP: u8 = 201;
@@ -1,9 +1,17 @@
-use super::State;
-use crate::read;
+use super::codec::Codec;
+use super::environment::Variable;
+#[cfg(feature = "gmcp")]
+use super::gmcp::GmcpMessage;
+use super::msdp::MsdpValue;
+use super::state::Bytes;
+use super::{option, Event, State, WindowSize};
+use crate::read::{self, CancelWaker, LineRead, ShutdownToken};
 use std::{
-    io::{self, ErrorKind, Read, Result, Write},
-    net::TcpStream,
-    sync::{Arc, Mutex},
+    collections::BTreeMap,
+    io::{self, Error, ErrorKind, Read, Result, Write},
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
 };
 
 /// Handles the TCP connection for a TELNET service, allowing reading and
@@ -11,19 +19,31 @@ use std::{
 ///
 /// Implements [`std::io::Read`] and [`std::io::Write`] to receive and send
 /// messages from/to the connection.
+///
+/// The TCP stream is blocking: the listener sleeps in `read` until data
+/// arrives, and readers waiting for a line or an answer of the other side
+/// sleep until the listener has handled it. So idle sessions don't use any
+/// CPU.
 #[derive(Clone)]
 pub struct Session {
     /// Reference to a TELNET connection [`State`]
     state: Arc<Mutex<State>>,
-    /// Refence to the TCP connection
+    /// Notified whenever the listener has handled incoming data or the session
+    /// has been closed. Used together with `state`.
+    state_changed: Arc<Condvar>,
+    /// Refence to the TCP connection, used for writing
     tcp_stream: Arc<Mutex<TcpStream>>,
+    /// Clone of the TCP connection, used by the listener only. It isn't
+    /// locked, so the listener may block on it without keeping others from
+    /// writing.
+    reader: Arc<TcpStream>,
 }
 
 impl Session {
     /// Creates new [`Session`] based on given [`TcpStream`] and a fresh
     /// [`State`].
-    /// Also ensures that the TCP stream is non-blocking as otherwise the
-    /// session becomes unusable.
+    /// Also ensures that the TCP stream is blocking, as the listener would
+    /// spin otherwise.
     ///
     /// # Arguments
     ///
@@ -34,25 +54,521 @@ impl Session {
     /// # Returns
     ///
     /// * `Ok(Self)` on success
-    /// * `Err(std::io::Error)` if `tcp_stream` cannot be set to non-blocking
+    /// * `Err(std::io::Error)` if `tcp_stream` cannot be set to blocking or
+    ///   cannot be cloned
     pub fn new(state: State, tcp_stream: TcpStream) -> Result<Self> {
-        tcp_stream.set_nonblocking(true)?;
+        tcp_stream.set_nonblocking(false)?;
+        let reader = tcp_stream.try_clone()?;
 
         Ok(Self {
             state: Arc::new(Mutex::new(state)),
+            state_changed: Arc::new(Condvar::new()),
             tcp_stream: Arc::new(Mutex::new(tcp_stream)),
+            reader: Arc::new(reader),
+        })
+    }
+
+    /// Requests the other side to let us enable `option` by sending WILL.
+    /// Does nothing if the option is already enabled or being negotiated.
+    ///
+    /// Notice that this only _starts_ the negotiation. Use
+    /// [`Session::wait_local`] to wait for the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::{thread, time::Duration};
+    /// use telnet_server::telnet::{option, Session, State, StateConfig};
+    ///
+    /// let session = Session::new(State::new(&StateConfig::default()), tcp_stream)?;
+    ///
+    /// let session_listen = session.clone();
+    /// thread::spawn(move || session_listen.listen());
+    ///
+    /// session.request_local(option::ECHO)?;
+    /// let is_echoing = session.wait_local(option::ECHO, Duration::from_secs(1))?;
+    ///
+    /// Ok(())
+    /// ```
+    pub fn request_local(&self, option: u8) -> Result<()> {
+        self.send(|state| state.enable_local(option))
+    }
+
+    /// Requests the other side to enable `option` by sending DO. Does nothing
+    /// if the option is already enabled or being negotiated.
+    ///
+    /// Notice that this only _starts_ the negotiation. Use
+    /// [`Session::wait_remote`] to wait for the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub fn request_remote(&self, option: u8) -> Result<()> {
+        self.send(|state| state.enable_remote(option))
+    }
+
+    /// Blocks until the negotiation of `option` on our side has finished.
+    /// Requires [`Session::listen`] to run in the background, as otherwise the
+    /// answer of the other side is never handled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` whether the option is enabled after the negotiation
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the other
+    ///   side didn't answer in time
+    pub fn wait_local(&self, option: u8, timeout: Duration) -> Result<bool> {
+        self.wait_for_answer(timeout, |state| {
+            if state.is_local_pending(option) {
+                None
+            } else {
+                Some(state.is_local_enabled(option))
+            }
+        })
+    }
+
+    /// Blocks until the negotiation of `option` on the other side has
+    /// finished. Requires [`Session::listen`] to run in the background, as
+    /// otherwise the answer of the other side is never handled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` whether the option is enabled after the negotiation
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the other
+    ///   side didn't answer in time
+    pub fn wait_remote(&self, option: u8, timeout: Duration) -> Result<bool> {
+        self.wait_for_answer(timeout, |state| {
+            if state.is_remote_pending(option) {
+                None
+            } else {
+                Some(state.is_remote_enabled(option))
+            }
+        })
+    }
+
+    /// Returns the next received [`Event`] that hasn't been polled yet, e.g.
+    /// a [`super::Signal`] sent by the client.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::{Event, Session, Signal, State, StateConfig};
+    ///
+    /// let session = Session::new(State::new(&StateConfig::default()), tcp_stream)?;
+    ///
+    /// // set up session to receive data...
+    ///
+    /// while let Some(event) = session.poll_event() {
+    ///     if event == Event::Signal(Signal::Abort) {
+    ///         // stop current action...
+    ///     }
+    /// }
+    ///
+    /// Ok(())
+    /// ```
+    pub fn poll_event(&self) -> Option<Event> {
+        self.state.lock().expect("Should lock state").poll_event()
+    }
+
+    /// Returns the last size of the terminal window that has been sent by the
+    /// client. Requires NAWS to be enabled, e.g. by calling
+    /// [`Session::request_remote`] with [`super::option::NAWS`]. Changes are
+    /// also announced via [`Event::WindowSize`].
+    ///
+    /// # Returns
+    ///
+    /// * `Some(WindowSize)` if the client has sent its window size
+    /// * `None` if NAWS is disabled or the client hasn't sent its size yet
+    pub fn window_size(&self) -> Option<WindowSize> {
+        self.state.lock().expect("Should lock state").window_size()
+    }
+
+    /// Returns the character set that has been agreed on via CHARSET, if any.
+    /// Requires CHARSET to be enabled, e.g. by calling
+    /// [`Session::request_remote`] with [`super::option::CHARSET`]. Once both
+    /// sides have agreed, [`Event::Charset`] is announced.
+    pub fn charset(&self) -> Option<String> {
+        self.state
+            .lock()
+            .expect("Should lock state")
+            .charset()
+            .map(str::to_string)
+    }
+
+    /// Returns the [`Codec`] that translates between Unicode text and the
+    /// character set of the client. Unless set via [`StateConfig::codec`] or
+    /// [`Session::set_codec`], it follows the character set agreed on via
+    /// CHARSET or the one suggested by the client's terminal type.
+    ///
+    /// [`StateConfig::codec`]: super::StateConfig::codec
+    pub fn codec(&self) -> Arc<dyn Codec> {
+        self.state.lock().expect("Should lock state").codec()
+    }
+
+    /// Sets the [`Codec`] that translates between Unicode text and the
+    /// character set of the client, e.g. after asking the user
+    pub fn set_codec(&self, codec: Arc<dyn Codec>) {
+        self.state
+            .lock()
+            .expect("Should lock state")
+            .set_codec(codec);
+    }
+
+    /// Returns every terminal type that the client has sent so far. Requires
+    /// TERMINAL-TYPE to be enabled, e.g. by calling
+    /// [`Session::request_remote`] with [`super::option::TERMINAL_TYPE`]. Once
+    /// the client has sent all of them, [`Event::TerminalTypes`] is announced.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::terminal_type::{mtts, MTTS_256_COLORS};
+    ///
+    /// let terminal_types = session.terminal_types();
+    /// let supports_256_colors = mtts(&terminal_types)
+    ///     .map(|flags| flags & MTTS_256_COLORS != 0)
+    ///     .unwrap_or(false);
+    /// ```
+    pub fn terminal_types(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .expect("Should lock state")
+            .terminal_types()
+            .to_vec()
+    }
+
+    /// Returns every environment variable that the client has sent so far.
+    /// Requires NEW-ENVIRON to be enabled, e.g. by calling
+    /// [`Session::request_remote`] with [`super::option::NEW_ENVIRON`]. Changes
+    /// are announced via [`Event::Environment`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let user = session.environment().get("USER").cloned();
+    /// ```
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        self.state
+            .lock()
+            .expect("Should lock state")
+            .environment()
+            .clone()
+    }
+
+    /// Requests specific environment variables from the client. All variables
+    /// are already requested once the client enables NEW-ENVIRON, so this is
+    /// only necessary to get the current value of variables that may have
+    /// changed. Does nothing if NEW-ENVIRON is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `variables` - [`Variable`]s that should be requested
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub fn request_environment(&self, variables: &[Variable]) -> Result<()> {
+        self.send(|state| state.request_environment(variables))
+    }
+
+    /// Requests the view of the client on the enabled options, which is
+    /// announced via [`Event::Status`]. Requires STATUS to be enabled on the
+    /// other side, e.g. by calling [`Session::request_remote`] with
+    /// [`super::option::STATUS`]. Otherwise nothing is sent.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the request cannot be sent
+    pub fn request_status(&self) -> Result<()> {
+        self.send(|state| state.request_status())
+    }
+
+    /// Sends an MSDP variable to the client, alongside the regular text.
+    /// Requires MSDP to be enabled on our side, e.g. by calling
+    /// [`Session::request_local`] with [`super::option::MSDP`]. Otherwise
+    /// nothing is sent. Variables of the client are announced via
+    /// [`Event::Msdp`].
+    ///
+    /// # Arguments
+    ///
+    /// * `variable` - Name of the variable, e.g. `HEALTH`
+    /// * `value` - [`MsdpValue`] of the variable
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the variable cannot be sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::MsdpValue;
+    ///
+    /// session.send_msdp("HEALTH", &MsdpValue::String("100".to_string()))?;
+    /// ```
+    pub fn send_msdp(&self, variable: &str, value: &MsdpValue) -> Result<()> {
+        self.send(|state| state.encode_msdp(variable, value))
+    }
+
+    /// Sends a GMCP message to the client, alongside the regular text.
+    /// Requires GMCP to be enabled on our side, e.g. by calling
+    /// [`Session::request_local`] with [`super::option::GMCP`]. Otherwise
+    /// nothing is sent. Messages of the client are announced via
+    /// [`Event::Gmcp`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the message cannot be sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use serde_json::json;
+    /// use telnet_server::telnet::GmcpMessage;
+    ///
+    /// session.send_gmcp(&GmcpMessage {
+    ///     name: "Char.Vitals".to_string(),
+    ///     data: Some(json!({ "hp": 100, "maxhp": 120 })),
+    /// })?;
+    /// ```
+    #[cfg(feature = "gmcp")]
+    pub fn send_gmcp(&self, message: &GmcpMessage) -> Result<()> {
+        self.send(|state| state.encode_gmcp(message))
+    }
+
+    /// Writes `text` translated into the character set of the client (see
+    /// [`Session::codec`]). Like [`std::io::Write::write`], a trailing
+    /// carriage return is only sent on the next write or flush.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text that should be shown to the user
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the text cannot be sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::codec::Cp437;
+    /// use std::sync::Arc;
+    ///
+    /// session.set_codec(Arc::new(Cp437));
+    /// session.write_str("╔══╗\n╚══╝\n")?;
+    /// session.flush()?;
+    /// ```
+    pub fn write_str(&self, text: &str) -> Result<()> {
+        self.send(|state| {
+            let text = state.codec().encode(text);
+            Some(state.encode(&text))
+        })
+    }
+
+    /// Writes a prompt and marks its end with "IAC EOR" or "IAC GA", depending
+    /// on the options the client has accepted (see [`State::encode_prompt`]).
+    /// This lets MUD clients and screen readers detect the prompt even though
+    /// it doesn't end with a line break.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - Prompt that should be shown to the user
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the prompt cannot be sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::option;
+    ///
+    /// session.request_local(option::END_OF_RECORD)?;
+    ///
+    /// session.write_prompt("> ")?;
+    /// let incoming = session.read_line_waiting()?;
+    /// ```
+    pub fn write_prompt(&self, prompt: &str) -> Result<()> {
+        self.send(|state| {
+            let prompt = state.codec().encode(prompt);
+            Some(state.encode_prompt(&prompt))
+        })
+    }
+
+    /// Measures the round-trip time to the client by sending "DO TIMING-MARK"
+    /// and waiting for its answer. As the client only answers after it has
+    /// handled everything sent before, this can also be used to ensure that
+    /// all output has reached the client.
+    ///
+    /// Requires [`Session::listen`] to run in the background, as otherwise the
+    /// answer of the client is never handled.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Duration)` round-trip time
+    /// * `Err(std::io::Error)` of kind [`ErrorKind::TimedOut`] if the client
+    ///   didn't answer in time, or any other error if the request cannot be
+    ///   sent
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    ///
+    /// let latency = session.ping(Duration::from_secs(5))?;
+    /// println!("Latency: {} ms", latency.as_millis());
+    /// ```
+    pub fn ping(&self, timeout: Duration) -> Result<Duration> {
+        let mut number = 0;
+        let start = Instant::now();
+
+        self.send(|state| {
+            let (request, n) = state.request_timing_mark();
+            number = n;
+            Some(request)
+        })?;
+
+        self.wait_for_answer(timeout, |state| {
+            if state.timing_marks_answered() >= number {
+                Some(start.elapsed())
+            } else {
+                None
+            }
         })
     }
 
+    /// Logs the user out: announces the logout by sending "WILL LOGOUT",
+    /// waits for the client to acknowledge it and closes the session (see
+    /// [`Session::close`]). The session is closed even if the client doesn't
+    /// answer in time.
+    ///
+    /// Requires [`Session::listen`] to run in the background, as otherwise the
+    /// answer of the client is never handled.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum [`Duration`] to wait for the answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the logout cannot be sent or the session
+    ///   cannot be closed
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use std::time::Duration;
+    ///
+    /// session.write_str("You have been idle for too long.\n")?;
+    /// session.logout(Duration::from_secs(1))?;
+    /// ```
+    pub fn logout(&self, timeout: Duration) -> Result<()> {
+        self.request_local(option::LOGOUT)?;
+
+        match self.wait_local(option::LOGOUT, timeout) {
+            Err(e) if e.kind() != ErrorKind::TimedOut => return Err(e),
+            _ => {}
+        }
+
+        self.close()
+    }
+
+    /// Closes the session. This is done in the following sequence:
+    ///
+    /// 1. Pending output (e.g. a held back CR) is sent to the client
+    /// 2. The application is notified via [`Event::Closed`]. From now on,
+    ///    [`Session::is_closed`] is true and reading a line fails with
+    ///    [`ErrorKind::NotConnected`] once everything received has been read.
+    /// 3. The TCP stream is shut down, which also wakes up and ends
+    ///    [`Session::listen`]
+    ///
+    /// The listener runs this sequence on its own when the client has asked
+    /// to be logged out via LOGOUT or has closed the connection. Closing an
+    /// already closed session does nothing.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` on success
+    /// * `Err(std::io::Error)` if the TCP stream cannot be shut down
+    pub fn close(&self) -> Result<()> {
+        let mut tcp_stream = self.tcp_stream.lock().expect("Should lock stream");
+
+        let pending = {
+            let mut state = self.state.lock().expect("Should lock state");
+
+            if state.is_closed() {
+                return Ok(());
+            }
+
+            let pending = state.close();
+            self.state_changed.notify_all();
+            pending
+        };
+
+        /* The client may already be gone, so the output is only sent on a
+         * best-effort basis */
+        if let Some(data) = pending {
+            let _ = tcp_stream.write_all(&data).and_then(|_| tcp_stream.flush());
+        }
+
+        match tcp_stream.shutdown(Shutdown::Both) {
+            Err(e) if e.kind() != ErrorKind::NotConnected => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether the session has been closed, see [`Session::close`]
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().expect("Should lock state").is_closed()
+    }
+
     /// Listens to and handles incoming TCP data.
-    /// Should be called in a background thread as it blocks. As the internal
-    /// TCP stream is set to non-blocking, reading and writing on a cloned
-    /// [`Session`] is still possible.
+    /// Should be called in a background thread as it blocks until data
+    /// arrives. As it reads from its own handle of the TCP stream, reading and
+    /// writing on a cloned [`Session`] is still possible meanwhile.
+    ///
+    /// Once the client asks to be logged out via LOGOUT or closes the
+    /// connection, the session is closed (see [`Session::close`]). The session
+    /// is closed on errors as well, so readers waiting for a line don't wait
+    /// forever.
     ///
     /// # Returns
     ///
-    /// Only returns an `Err(std::io::Error)` on TCP errors as it runs
-    /// indefinitely.
+    /// * `Ok(())` once the session has been closed
+    /// * `Err(std::io::Error)` on TCP errors
     ///
     /// # Examples
     ///
@@ -72,48 +588,170 @@ impl Session {
     /// Ok(())
     /// ```
     pub fn listen(self) -> Result<()> {
-        let mut buf: [u8; 255] = [0; 255];
+        let result = self.receive();
+        let closed = self.close();
+        result.and(closed)
+    }
+
+    /// Handles incoming TCP data until the client closes the connection or
+    /// asks to be logged out
+    fn receive(&self) -> Result<()> {
+        let mut buf: [u8; 1024] = [0; 1024];
 
         loop {
-            let mut tcp_stream = match self.tcp_stream.try_lock() {
-                Ok(t) => t,
-                Err(_) => continue,
+            let tcp_data = match (&*self.reader).read(&mut buf) {
+                Ok(read_bytes) => &buf[..read_bytes],
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
             };
 
-            let tcp_data = match tcp_stream.read(&mut buf) {
-                Ok(read_bytes) => &buf[..read_bytes],
-                Err(e) => {
-                    if e.kind() == ErrorKind::WouldBlock {
-                        continue;
-                    }
+            /* Nothing is read once the connection has been closed */
+            if tcp_data.is_empty() {
+                return Ok(());
+            }
 
-                    return Err(e);
-                }
+            /* Like for sending, the stream is locked before the state */
+            let mut tcp_stream = self.tcp_stream.lock().expect("Should lock stream");
+
+            let (response, is_logging_out) = {
+                let mut state = self.state.lock().expect("Should lock state");
+                let response = state.write(tcp_data);
+                self.state_changed.notify_all();
+                (response?, state.is_local_enabled(option::LOGOUT))
             };
 
-            if let Some(telnet_data) = self
-                .state
-                .lock()
-                .expect("Should lock state")
-                .write(tcp_data)?
-            {
+            if let Some(telnet_data) = response {
                 tcp_stream.write_all(&telnet_data)?;
                 tcp_stream.flush()?;
             }
+
+            if is_logging_out {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends the data (if any) that `produce` returns for the locked
+    /// [`State`]. Like the listener, the stream is locked before the state, so
+    /// data is sent in the same order as the state has produced it. This is
+    /// required as soon as the data is compressed.
+    fn send<F>(&self, produce: F) -> Result<()>
+    where
+        F: FnOnce(&mut State) -> Option<Bytes>,
+    {
+        let mut tcp_stream = self.tcp_stream.lock().expect("Should lock stream");
+
+        let data = match produce(&mut self.state.lock().expect("Should lock state")) {
+            Some(d) => d,
+            None => return Ok(()),
+        };
+
+        tcp_stream.write_all(&data)?;
+        tcp_stream.flush()
+    }
+
+    /// Waits until `check` returns a result for the [`State`] or `timeout`
+    /// is reached. The state is checked again whenever the listener has
+    /// handled incoming data.
+    fn wait_for_answer<T, F>(&self, timeout: Duration, check: F) -> Result<T>
+    where
+        F: Fn(&State) -> Option<T>,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().expect("Should lock state");
+
+        loop {
+            if let Some(result) = check(&state) {
+                return Ok(result);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "Other side has not answered in time",
+                ));
+            }
+
+            state = self
+                .state_changed
+                .wait_timeout(state, remaining)
+                .expect("Should lock state")
+                .0;
+        }
+    }
+}
+
+impl Session {
+    /// Waits until the listener has received a complete line, the session
+    /// has been closed, `deadline` has passed or `token` has been cancelled
+    fn read_line_until(
+        &self,
+        deadline: Option<Instant>,
+        token: Option<&ShutdownToken>,
+    ) -> LineRead {
+        /* Registered before locking the state, so a cancellation either
+         * happens before the check below or wakes us up afterwards. It's
+         * kept alive until we return. */
+        let _waker = token.map(|token| {
+            let state = self.state.clone();
+            let state_changed = self.state_changed.clone();
+            let waker: CancelWaker = Arc::new(move || {
+                let _state = state.lock().expect("Should lock state");
+                state_changed.notify_all();
+            });
+            token.on_cancel(&waker);
+            waker
+        });
+
+        let mut state = self.state.lock().expect("Should lock state");
+
+        loop {
+            /* Characters may span several bytes, so the line is decoded as
+             * a whole */
+            if let Some(line) = state.read_line() {
+                return LineRead::Line(state.codec().decode(&line));
+            }
+
+            /* Everything received before closing has been read */
+            if state.is_closed() {
+                return LineRead::Disconnected;
+            }
+
+            if token.is_some_and(ShutdownToken::is_cancelled) {
+                return LineRead::Cancelled;
+            }
+
+            state = match deadline {
+                None => self.state_changed.wait(state).expect("Should lock state"),
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+
+                    if remaining.is_zero() {
+                        return LineRead::TimedOut;
+                    }
+
+                    self.state_changed
+                        .wait_timeout(state, remaining)
+                        .expect("Should lock state")
+                        .0
+                }
+            };
         }
     }
 }
 
+/// Writes application data, encoded for TELNET (see [`State::encode`]). So any
+/// [`String`] can be written without worrying about escaping or line breaks.
 impl io::Write for Session {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.tcp_stream
-            .lock()
-            .expect("Should lock stream")
-            .write(buf)
+        self.send(|state| Some(state.encode(buf)))?;
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> Result<()> {
-        self.tcp_stream.lock().expect("Should lock stream").flush()
+        self.send(|state| state.flush_encoding())
     }
 }
 
@@ -125,26 +763,113 @@ impl io::Read for Session {
 
 impl read::Read for Session {
     fn read_line_waiting(&mut self) -> Result<String> {
-        let mut line = String::new();
-        let mut buf: [u8; 1] = [0];
-
-        loop {
-            match self.read(&mut buf) {
-                Ok(1) => {
-                    let next = buf[0] as char;
-                    line.push(next);
-                    if next == '\n' {
-                        break;
-                    }
-                }
-                Ok(0) => {
-                    continue;
-                }
-                Ok(_) => panic!("Out of range"),
-                Err(e) => return Err(e),
-            };
+        match self.read_line_until(None, None) {
+            LineRead::Line(line) => Ok(line),
+            /* Neither a deadline nor a token has been given */
+            _ => Err(Error::new(
+                ErrorKind::NotConnected,
+                "Session has been closed",
+            )),
         }
+    }
+
+    fn read_line_timeout(&mut self, timeout: Duration) -> Result<LineRead> {
+        Ok(self.read_line_until(Some(Instant::now() + timeout), None))
+    }
+
+    fn read_line_cancellable(&mut self, token: &ShutdownToken) -> Result<LineRead> {
+        Ok(self.read_line_until(None, Some(token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::Read as _;
+    use crate::telnet::StateConfig;
+    use std::{net::TcpListener, thread};
+
+    /// Returns a listening [`Session`] and the client side of its connection
+    fn connect() -> (Session, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind");
+        let client = TcpStream::connect(listener.local_addr().unwrap()).expect("Should connect");
+        let (server, _) = listener.accept().expect("Should accept");
+
+        let session = Session::new(State::new(&StateConfig::default()), server).unwrap();
+        let session_listen = session.clone();
+        thread::spawn(move || session_listen.listen());
+
+        (session, client)
+    }
+
+    #[test]
+    fn wakes_reader_once_line_arrives() {
+        let (mut session, mut client) = connect();
+
+        let reader = thread::spawn(move || session.read_line_waiting());
+        client.write_all(b"hel").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.write_all(b"lo\r\nmore\r\n").unwrap();
+
+        assert_eq!(reader.join().unwrap().unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn wakes_reader_once_client_disconnects() {
+        let (mut session, client) = connect();
+
+        let mut session_read = session.clone();
+        let reader = thread::spawn(move || session_read.read_line_waiting());
+        drop(client);
+
+        let error = reader.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotConnected);
+        assert!(session.is_closed());
+        assert!(session.read_line_waiting().is_err());
+    }
+
+    #[test]
+    fn wakes_waiter_once_other_side_answers() {
+        let (session, mut client) = connect();
+
+        session.request_local(option::ECHO).unwrap();
+        client.write_all(&[255, 253, option::ECHO]).unwrap();
+
+        let is_echoing = session.wait_local(option::ECHO, Duration::from_secs(5));
+        assert!(is_echoing.unwrap());
+    }
+
+    #[test]
+    fn keeps_incomplete_line_on_timeout() {
+        let (mut session, mut client) = connect();
+
+        client.write_all(b"hel").unwrap();
+        let result = session.read_line_timeout(Duration::from_millis(50));
+        assert_eq!(result.unwrap(), LineRead::TimedOut);
+
+        client.write_all(b"lo\r\n").unwrap();
+        let result = session.read_line_timeout(Duration::from_secs(5));
+        assert_eq!(result.unwrap(), LineRead::Line("hello\n".to_string()));
+
+        assert_eq!(session.try_read_line().unwrap(), LineRead::Pending);
+        drop(client);
+
+        let result = session.read_line_timeout(Duration::from_secs(5));
+        assert_eq!(result.unwrap(), LineRead::Disconnected);
+    }
+
+    #[test]
+    fn wakes_reader_once_cancelled() {
+        let (session, _client) = connect();
+        let token = ShutdownToken::new();
+
+        let mut session_read = session.clone();
+        let token_read = token.clone();
+        let reader = thread::spawn(move || session_read.read_line_cancellable(&token_read));
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
 
-        Ok(line)
+        assert_eq!(reader.join().unwrap().unwrap(), LineRead::Cancelled);
+        assert!(!session.is_closed());
     }
 }
@@ -1,28 +1,72 @@
+use super::charset::Charset;
+use super::codec::{self, Codec, Utf8};
+#[cfg(feature = "mccp")]
+use super::compression::{Compressor, Decompressor};
+use super::decoder::{Decoder, DecoderEvent};
+use super::environment::{Environment, Variable};
+use super::error::Error;
+use super::event::{Event, Signal};
+#[cfg(feature = "gmcp")]
+use super::gmcp::GmcpMessage;
+use super::handler::{OptionHandler, OptionHandlerFactory};
+use super::linemode::{Linemode, LinemodeConfig};
+use super::msdp::{self, MsdpValue};
+use super::mssp::{self, MsspProvider};
+use super::naws::WindowSize;
+use super::negotiation::Side;
+use super::option;
+use super::status;
+use super::terminal_type::TerminalType;
 use crate::iter::contains_sequence;
-use std::{
-    cmp::min,
-    io::{Error, Read},
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
 };
-
-const ECHO: u8 = 1;
-const ERASE_LINE: u8 = 248;
+use core::cmp::min;
 
 const BEL: u8 = 7;
 
+const CHAR_NUL: u8 = 0;
 const CHAR_BACK_SPACE: u8 = 8;
+const CHAR_LINE_FEED: u8 = 10;
+const CHAR_CARRIAGE_RETURN: u8 = 13;
 const CHAR_ESCAPE: u8 = 27;
 const CHAR_DELETE: u8 = 127;
-const CHAR_ERASE: u8 = 247;
 
 const IAC: u8 = 255;
-/// "IAC SB"
-const IAC_SUBNEGOTIATION_START: u8 = 250;
-/// "IAC SE"
-const IAC_SUBNEGOTIATION_END: u8 = 240;
-const IAC_WILL: u8 = 251;
-const IAC_WONT: u8 = 252;
-const IAC_DO: u8 = 253;
-const IAC_DONT: u8 = 254;
+/// "IAC NOP"
+const IAC_NO_OPERATION: u8 = 241;
+/// "IAC DM", data stream portion of a Synch
+const IAC_DATA_MARK: u8 = 242;
+/// "IAC BRK"
+const IAC_BREAK: u8 = 243;
+/// "IAC IP"
+const IAC_INTERRUPT_PROCESS: u8 = 244;
+/// "IAC AO"
+const IAC_ABORT_OUTPUT: u8 = 245;
+/// "IAC AYT"
+const IAC_ARE_YOU_THERE: u8 = 246;
+/// "IAC EC"
+const IAC_ERASE_CHARACTER: u8 = 247;
+/// "IAC EL"
+const IAC_ERASE_LINE: u8 = 248;
+/// "IAC GA"
+const IAC_GO_AHEAD: u8 = 249;
+/// "IAC EOR", see RFC-885
+const IAC_END_OF_RECORD: u8 = 239;
+/// "IAC EOF", see RFC-1184
+const IAC_END_OF_FILE: u8 = 236;
+/// "IAC SUSP", see RFC-1184
+const IAC_SUSPEND: u8 = 237;
+/// "IAC ABORT", see RFC-1184
+const IAC_ABORT: u8 = 238;
+
+/// Visible answer to "IAC AYT"
+const ARE_YOU_THERE_RESPONSE: &[u8] = b"\r\n[Yes]\r\n";
 
 /// Sequence for erasing current line in ANSI terminals
 const ANSI_SEQUENCE_ERASE_LINE: [u8; 5] = [CHAR_ESCAPE, 91, 50, 75, 13];
@@ -51,29 +95,111 @@ const CHARS_ESCAPE_SEQUENCE_END: [char; 20] = [
     'l', /* DECTCEM */
 ];
 
-const CHARS_LINE_BREAK: [u8; 2] = [b'\r', b'\n'];
+/// Options that we're willing to perform ourselves
+const SUPPORTED_LOCAL_OPTIONS: [u8; 7] = [
+    option::ECHO,
+    option::SUPPRESS_GO_AHEAD,
+    option::STATUS,
+    option::LOGOUT,
+    option::END_OF_RECORD,
+    option::CHARSET,
+    option::MSDP,
+];
+/// Options that we're willing to let the other side perform, regardless of the
+/// [`StateConfig`]
+const SUPPORTED_REMOTE_OPTIONS: [u8; 6] = [
+    option::SUPPRESS_GO_AHEAD,
+    option::STATUS,
+    option::NAWS,
+    option::TERMINAL_TYPE,
+    option::NEW_ENVIRON,
+    option::CHARSET,
+];
+
+/// Maximum length of a single sub negotiation. Any data beyond is discarded.
+const MAX_SUB_NEGOTIATION_LENGTH: usize = 64 * 1024;
 
 /// Type for read-only bytes
 pub type Bytes = Box<[u8]>;
 pub type BytesResult = Result<Option<Bytes>, Error>;
 
 /// Struct that holds and handles the current state of a TELNET session.
-/// Implements [`Read`] to get the handled readable, non-command data and a fake
-/// `Write` to accept incoming TCP data.
+/// Incoming TCP data is accepted via [`State::write`], the handled readable,
+/// non-command data is obtained via [`State::read_data`] (or
+/// [`std::io::Read`] with the `std` feature).
 ///
 /// # Notice
 ///
 /// This struct is independent of any service related input or TCP at all. It is
 /// just a bare state machine.
 pub struct State {
-    /// Buffer of "readable" received data that can be obtained by using the
-    /// [`Read`] trait
+    /// Buffer of "readable" received data that can be obtained via
+    /// [`State::read_data`]
     output_buffer: Vec<u8>,
     /// Current overall mode
     mode: Mode,
-    /// Indicates whether every incoming, non-command char should be echoed back
-    /// to the connection
-    is_echoing: bool,
+    /// Decodes the TELNET layer of incoming data and keeps track of the
+    /// negotiation state of every option on both sides
+    decoder: Decoder,
+    /// Data that has to be sent to the other side, before compression. Kept
+    /// between writes to reuse its allocation.
+    outgoing: Vec<u8>,
+    /// Un-escaped payload of the current subnegotiation, without the option
+    sub_negotiation_buffer: Vec<u8>,
+    /// Whether the current sub negotiation has exceeded
+    /// [`MAX_SUB_NEGOTIATION_LENGTH`] and is therefore discarded
+    is_sub_negotiation_overflowing: bool,
+    /// Handlers for options that aren't handled by this crate
+    option_handlers: Vec<Box<dyn OptionHandler>>,
+    /// State of the LINEMODE option
+    linemode: Linemode,
+    /// Last size of the terminal window that has been sent by the client
+    window_size: Option<WindowSize>,
+    /// State of the TERMINAL-TYPE option
+    terminal_type: TerminalType,
+    /// State of the NEW-ENVIRON option
+    environment: Environment,
+    /// State of the CHARSET option
+    charset: Charset,
+    /// [`Codec`] of the character set that the client uses
+    codec: Arc<dyn Codec>,
+    /// Whether the codec has been set explicitly and must not be changed by
+    /// CHARSET or the terminal type
+    is_codec_fixed: bool,
+    /// Provider of the server status that is sent via MSSP
+    mssp: Option<MsspProvider>,
+    /// Received events that haven't been polled yet
+    events: VecDeque<Event>,
+    /// Line terminator that incoming line breaks are translated to
+    line_terminator: LineTerminator,
+    /// Compresses outbound data while MCCP2 is active
+    #[cfg(feature = "mccp")]
+    compressor: Option<Compressor>,
+    /// Whether outbound compression starts after the current output
+    #[cfg(feature = "mccp")]
+    is_compression_starting: bool,
+    /// Whether outbound compression ends after the current output
+    #[cfg(feature = "mccp")]
+    is_compression_ending: bool,
+    /// Decompresses inbound data while MCCP3 is active
+    #[cfg(feature = "mccp")]
+    decompressor: Option<Decompressor>,
+    /// Whether inbound data is compressed after the current byte
+    #[cfg(feature = "mccp")]
+    is_decompression_starting: bool,
+    /// Number of timing marks that we've requested
+    timing_marks_requested: u64,
+    /// Number of our timing marks that the other side has answered
+    timing_marks_answered: u64,
+    /// Whether the session has been closed via [`State::close`]
+    is_closed: bool,
+    /// Whether the last received data byte has been a CR, so that a following
+    /// LF or NUL belongs to the same line break. Commands in between don't
+    /// matter.
+    is_receiving_carriage_return: bool,
+    /// Whether the last encoded byte has been a CR whose meaning depends on
+    /// the next byte
+    is_encoding_carriage_return: bool,
     /// If true, ANSI escape sequences will be handled like normal non-command
     /// input. Otherwise, sequences will be ignored and a BEL is sent back to
     /// notice.
@@ -87,24 +213,52 @@ pub struct StateConfig {
     /// input. Otherwise, sequences will be ignored and a BEL is sent back to
     /// notice.
     pub handle_ansi_escape_sequences: bool,
+    /// If set, the client may enable LINEMODE, which is then set up with this
+    /// configuration. Otherwise LINEMODE is refused.
+    pub linemode: Option<LinemodeConfig>,
+    /// Line terminator that every incoming line break (CR LF, CR NUL or a bare
+    /// LF) is translated to
+    pub line_terminator: LineTerminator,
+    /// Factories of handlers for additional options, see
+    /// [`super::handler::OptionHandler`]
+    pub option_handlers: Vec<OptionHandlerFactory>,
+    /// If true, the client may enable TRANSMIT-BINARY in both directions.
+    /// Otherwise, it can only be enabled on request of the server.
+    pub accept_binary: bool,
+    /// If set, the client's character set is always translated with this
+    /// [`Codec`]. Otherwise UTF-8 is used until another character set has been
+    /// agreed on via CHARSET or is suggested by the client's terminal type.
+    pub codec: Option<Arc<dyn Codec>>,
+    /// If set, the client may enable MSSP and gets the server status of this
+    /// provider. Otherwise MSSP is refused.
+    pub mssp: Option<MsspProvider>,
+}
+
+/// Line terminator that the application receives for every line break
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineTerminator {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
 }
 
-/// Enumeration of overall modes that a TELNET state may have
+impl LineTerminator {
+    /// Returns the bytes of the line terminator
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineTerminator::Lf => b"\n",
+            LineTerminator::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Enumeration of overall modes that the incoming, non-command data of a
+/// TELNET state may have. Commands are handled by the [`Decoder`].
 enum Mode {
     /// Incoming, non-command data (e.g. text)
     Idle,
-    /// Incoming command data (e.g. WILL, WONT, DO, DONT)
-    Command,
-    /// Incoming command data for WILL command
-    CommandWill,
-    /// Incoming command data for WONT command
-    CommandWont,
-    /// Incoming command data for DO command
-    CommandDo,
-    /// Incoming command data for DONT command
-    CommandDont,
-    /// Incoming command data for sub negotiation command
-    SubNegotiation,
     /// Incoming escape sequence. This is not a "real" mode but we need it as
     /// you can choose to ignore ANSI escape sequences because it doesn't really
     /// make sense to evaluate these.
@@ -132,10 +286,70 @@ impl State {
     /// let state = State::new(&config);
     /// ```
     pub fn new(config: &StateConfig) -> Self {
+        let mut supported_remote_options = SUPPORTED_REMOTE_OPTIONS.to_vec();
+
+        let mut supported_local_options = SUPPORTED_LOCAL_OPTIONS.to_vec();
+
+        if config.linemode.is_some() {
+            supported_remote_options.push(option::LINEMODE);
+        }
+
+        if config.accept_binary {
+            supported_local_options.push(option::BINARY);
+            supported_remote_options.push(option::BINARY);
+        }
+
+        if config.mssp.is_some() {
+            supported_local_options.push(option::MSSP);
+        }
+
+        #[cfg(feature = "mccp")]
+        supported_local_options.extend([option::COMPRESS2, option::COMPRESS3]);
+
+        #[cfg(feature = "gmcp")]
+        supported_local_options.push(option::GMCP);
+
+        let option_handlers: Vec<_> = config.option_handlers.iter().map(|f| f()).collect();
+
+        let mut decoder = Decoder::new(&supported_local_options, &supported_remote_options);
+        if option_handlers.iter().any(|h| h.option() == option::NAWS) {
+            /* The handler gets the raw sub negotiations instead */
+            decoder.set_decoding_window_size(false);
+        }
+
         State {
             output_buffer: vec![],
             mode: Mode::Idle,
-            is_echoing: false,
+            decoder,
+            outgoing: vec![],
+            sub_negotiation_buffer: vec![],
+            is_sub_negotiation_overflowing: false,
+            option_handlers,
+            linemode: Linemode::new(config.linemode.clone().unwrap_or_default()),
+            window_size: None,
+            terminal_type: TerminalType::default(),
+            environment: Environment::default(),
+            charset: Charset::default(),
+            mssp: config.mssp.clone(),
+            codec: config.codec.clone().unwrap_or_else(|| Arc::new(Utf8)),
+            is_codec_fixed: config.codec.is_some(),
+            events: VecDeque::new(),
+            line_terminator: config.line_terminator,
+            #[cfg(feature = "mccp")]
+            compressor: None,
+            #[cfg(feature = "mccp")]
+            is_compression_starting: false,
+            #[cfg(feature = "mccp")]
+            is_compression_ending: false,
+            #[cfg(feature = "mccp")]
+            decompressor: None,
+            #[cfg(feature = "mccp")]
+            is_decompression_starting: false,
+            timing_marks_requested: 0,
+            timing_marks_answered: 0,
+            is_closed: false,
+            is_receiving_carriage_return: false,
+            is_encoding_carriage_return: false,
             handle_ansi_escape_sequences: config.handle_ansi_escape_sequences,
         }
     }
@@ -151,7 +365,7 @@ impl State {
     ///
     /// # Returns
     ///
-    /// * `Err(std::io::Error)` if an error occurs. In this case the internal
+    /// * `Err(Error)` if an error occurs. In this case the internal
     ///   TELNET state possibly mismatches the "real" state. This _should_ lead
     ///   to the termination of the TELNET session at all.
     /// * `Ok(Some([u8]))` if data should be sent back
@@ -173,28 +387,14 @@ impl State {
     ///
     /// // write back result to TCP connection...
     ///
-    /// Ok::<(), std::io::Error>(())
+    /// Ok::<(), telnet_server::telnet::Error>(())
     /// ```
     pub fn write(&mut self, buf: &[u8]) -> BytesResult {
         let mut response: Vec<u8> = vec![];
 
-        for &next in buf {
-            let result = match self.mode {
-                Mode::Idle => self.next_on_idle(next),
-                Mode::Command => self.next_as_command(next),
-                Mode::CommandWill => self.next_as_will(next),
-                Mode::CommandWont => self.next_as_wont(next),
-                Mode::CommandDo => self.next_as_do(next),
-                Mode::CommandDont => self.next_as_dont(next),
-                Mode::SubNegotiation => self.next_as_sub_negotiation(next),
-                Mode::AnsiEscapeSequence => self.next_as_escape_sequence(next),
-            };
-
-            if let Ok(Some(v)) = result {
-                response.extend_from_slice(&v);
-            } else if result.is_err() {
-                return result;
-            }
+        if let Err(error) = self.handle(buf, &mut response) {
+            self.outgoing.clear();
+            return Err(error);
         }
 
         if !response.is_empty() {
@@ -204,268 +404,2180 @@ impl State {
         }
     }
 
-    /// Handles incoming `next` byte when [`State`] is in idle mode
+    /// Requests to enable `option` on our side by sending WILL. Does nothing
+    /// if the option is already enabled or a negotiation is pending that
+    /// results in it being enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_on_idle(&mut self, next: u8) -> BytesResult {
-        match next {
-            IAC => self.mode = Mode::Command,
-            CHAR_DELETE | CHAR_BACK_SPACE | CHAR_ERASE => {
-                self.output_buffer.pop();
-
-                if self.is_echoing {
-                    /* Return fake backspace on echo mode */
-                    return Ok(Some(Box::new([CHAR_BACK_SPACE, b' ', CHAR_BACK_SPACE])));
-                }
-            }
-            ERASE_LINE => {
-                Self::erase_current_line(&mut self.output_buffer);
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// let request = state.enable_local(option::ECHO);
+    /// assert_eq!(request.as_deref(), Some(&[255, 251, 1][..]));
+    ///
+    /// // Still waiting for an answer, so nothing has to be sent again
+    /// assert!(state.enable_local(option::ECHO).is_none());
+    /// ```
+    pub fn enable_local(&mut self, option: u8) -> Option<Bytes> {
+        self.decoder.enable_local(option, &mut self.outgoing);
+        self.take_outgoing()
+    }
 
-                if self.is_echoing {
-                    return Ok(Some(ANSI_SEQUENCE_ERASE_LINE.into()));
-                }
+    /// Requests to disable `option` on our side by sending WONT. Does nothing
+    /// if the option is already disabled or a negotiation is pending that
+    /// results in it being disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    pub fn disable_local(&mut self, option: u8) -> Option<Bytes> {
+        self.request_disable(Side::Local, option)
+    }
 
-                return Ok(None);
-            }
-            CHAR_ESCAPE => {
-                self.mode = Mode::AnsiEscapeSequence;
+    /// Requests the other side to enable `option` by sending DO. Does nothing
+    /// if the option is already enabled or a negotiation is pending that
+    /// results in it being enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    pub fn enable_remote(&mut self, option: u8) -> Option<Bytes> {
+        self.decoder.enable_remote(option, &mut self.outgoing);
+        self.take_outgoing()
+    }
 
-                if self.is_echoing {
-                    return Ok(Some(Box::new([next])));
-                }
+    /// Requests the other side to disable `option` by sending DONT. Does
+    /// nothing if the option is already disabled or a negotiation is pending
+    /// that results in it being disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option, see [`super::option`]
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    pub fn disable_remote(&mut self, option: u8) -> Option<Bytes> {
+        self.request_disable(Side::Remote, option)
+    }
 
-                if self.handle_ansi_escape_sequences {
-                    self.output_buffer.push(next);
-                }
-            }
-            _ => {
-                self.output_buffer.push(next);
+    /// Returns whether `option` is currently enabled on our side
+    pub fn is_local_enabled(&self, option: u8) -> bool {
+        self.decoder.is_enabled(Side::Local, option)
+    }
 
-                if self.is_echoing {
-                    return Ok(Some(Box::new([next])));
-                }
-            }
-        }
+    /// Returns whether `option` is currently enabled on the other side
+    pub fn is_remote_enabled(&self, option: u8) -> bool {
+        self.decoder.is_enabled(Side::Remote, option)
+    }
 
-        Ok(None)
+    /// Returns every option that is currently enabled on given `side`, in
+    /// ascending order. The view of the client can be requested via
+    /// [`State::request_status`] to compare both.
+    pub fn enabled_options(&self, side: Side) -> Vec<u8> {
+        self.decoder.options().enabled(side)
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC mode
-    ///
-    /// # Returns
-    ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_command(&mut self, next: u8) -> BytesResult {
-        match next {
-            IAC_WILL => self.mode = Mode::CommandWill,
-            IAC_WONT => self.mode = Mode::CommandWont,
-            IAC_DO => self.mode = Mode::CommandDo,
-            IAC_DONT => self.mode = Mode::CommandDont,
-            IAC_SUBNEGOTIATION_START => self.mode = Mode::SubNegotiation,
-            _ => {
-                return Err(Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!("Unknown command '{next}'"),
-                ))
-            }
-        };
+    /// Returns whether we're still waiting for the other side to answer a
+    /// negotiation of `option` on our side
+    pub fn is_local_pending(&self, option: u8) -> bool {
+        self.decoder.options().is_pending(Side::Local, option)
+    }
 
-        Ok(None)
+    /// Returns whether we're still waiting for the other side to answer a
+    /// negotiation of `option` on its side
+    pub fn is_remote_pending(&self, option: u8) -> bool {
+        self.decoder.options().is_pending(Side::Remote, option)
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC WILL mode
+    /// Encodes outgoing application data so that it can be sent to the other
+    /// side. Every IAC is escaped. Unless TRANSMIT-BINARY is enabled on our
+    /// side, a `\n` is sent as CR LF and a bare CR is sent as CR NUL, as
+    /// required by RFC-854.
+    ///
+    /// A trailing CR is held back until the next call (or
+    /// [`State::flush_encoding`]) as it may be followed by a `\n`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Application data that should be sent
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_will(&mut self, _next: u8) -> BytesResult {
-        /* Ignore message, just go back to idle state */
-        self.mode = Mode::Idle;
-        Ok(None)
+    /// Data that can be written to the connection
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// assert_eq!(&*state.encode(b"a\nb\r\n\xff"), b"a\r\nb\r\n\xff\xff");
+    ///
+    /// assert_eq!(&*state.encode(b"c\r"), b"c");
+    /// assert_eq!(state.flush_encoding().as_deref(), Some(&b"\r\0"[..]));
+    /// ```
+    pub fn encode(&mut self, data: &[u8]) -> Bytes {
+        self.encode_data(data);
+        self.take_outgoing().unwrap_or_default()
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC WONT mode
+    /// Returns a CR that has been held back by [`State::encode`], if any
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_wont(&mut self, _next: u8) -> BytesResult {
-        /* Ignore message, just go back to idle state */
-        self.mode = Mode::Idle;
-        Ok(None)
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has been held back
+    pub fn flush_encoding(&mut self) -> Option<Bytes> {
+        self.flush_carriage_return();
+        self.take_outgoing()
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC DO mode
+    /// Encodes a prompt like [`State::encode`] and marks its end, so that
+    /// clients can tell where the prompt ends even without a line break. The
+    /// marker is "IAC EOR" if END-OF-RECORD is enabled on our side, "IAC GA"
+    /// unless SUPPRESS-GO-AHEAD is enabled on our side, and nothing otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - Application data of the prompt
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_do(&mut self, next: u8) -> BytesResult {
-        self.mode = Mode::Idle;
+    /// Data that can be written to the connection
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC GA
+    /// assert_eq!(&*state.encode_prompt(b"> "), b"> \xff\xf9");
+    /// ```
+    pub fn encode_prompt(&mut self, prompt: &[u8]) -> Bytes {
+        self.encode_data(prompt);
+        self.flush_carriage_return();
 
-        if next == ECHO {
-            self.is_echoing = true;
-            return Ok(Some(Box::new([IAC, IAC_WILL, ECHO])));
+        if self.is_local_enabled(option::END_OF_RECORD) {
+            self.outgoing.extend_from_slice(&[IAC, IAC_END_OF_RECORD]);
+        } else if !self.is_local_enabled(option::SUPPRESS_GO_AHEAD) {
+            self.outgoing.extend_from_slice(&[IAC, IAC_GO_AHEAD]);
         }
 
-        /* Whatever they're asking for, we're not supporting it probably. */
-        Ok(Some(Box::new([IAC, IAC_WONT, next])))
+        self.take_outgoing().unwrap_or_default()
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC DONT mode
+    /// Requests a timing mark from the other side by sending "DO TIMING-MARK".
+    /// The other side answers once it has handled all data sent before.
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_dont(&mut self, next: u8) -> BytesResult {
-        self.mode = Mode::Idle;
+    /// The data that has to be sent and the number of the timing mark. Once
+    /// [`State::timing_marks_answered`] reaches this number, the timing mark
+    /// has been answered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// let (request, number) = state.request_timing_mark();
+    /// assert_eq!(&*request, &[255, 253, option::TIMING_MARK]);
+    ///
+    /// // IAC WILL TIMING-MARK
+    /// state.write(&[255, 251, option::TIMING_MARK])?;
+    /// assert!(state.timing_marks_answered() >= number);
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn request_timing_mark(&mut self) -> (Bytes, u64) {
+        self.timing_marks_requested += 1;
+        self.decoder.request_timing_mark(&mut self.outgoing);
 
-        if next == ECHO {
-            self.is_echoing = false;
-        }
+        (
+            self.take_outgoing().unwrap_or_default(),
+            self.timing_marks_requested,
+        )
+    }
 
-        /* Whatever they're asking for, we're not supporting it probably.
-         * So it's fine to say that we won't do it. */
-        Ok(Some(Box::new([IAC, IAC_WONT, next])))
+    /// Returns the number of timing marks requested via
+    /// [`State::request_timing_mark`] that the other side has answered
+    pub fn timing_marks_answered(&self) -> u64 {
+        self.timing_marks_answered
     }
 
-    /// Handles incoming `next` byte when [`State`] is in IAC SB mode
+    /// Closes the session. Returns the output that is still pending, which has
+    /// to be sent before the connection is shut down, and announces
+    /// [`Event::Closed`]. Closing an already closed session does nothing.
     ///
     /// # Returns
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_sub_negotiation(&mut self, next: u8) -> BytesResult {
-        /* We're NOT handling sub negotiations right now. */
-        if next == IAC_SUBNEGOTIATION_END {
-            self.mode = Mode::Idle;
-        }
-
-        Ok(None)
-    }
-
-    /// Handles incoming `next` byte when [`State`] is in idle mode and in an
-    /// ANSI escape sequence
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing is pending or the session is already closed
     ///
-    /// # Returns
+    /// # Examples
     ///
-    /// * `Ok(None)` - Everythings okay, no need to write something back
-    /// * `Ok(Some(Bytes))` - Everythings okay, something has to be written back
-    /// * `Err` - Data could not be interpreted
-    fn next_as_escape_sequence(&mut self, next: u8) -> BytesResult {
-        if self.handle_ansi_escape_sequences {
-            self.output_buffer.push(next);
+    /// ```rust
+    /// use telnet_server::telnet::{option, Event, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC DO LOGOUT, the client wants to be logged out
+    /// state.write(&[255, 253, option::LOGOUT])?;
+    /// assert!(state.is_local_enabled(option::LOGOUT));
+    ///
+    /// let pending = state.close();
+    /// // write back pending data and shut down the TCP connection...
+    ///
+    /// assert_eq!(state.poll_event(), Some(Event::Closed));
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn close(&mut self) -> Option<Bytes> {
+        if self.is_closed {
+            return None;
+        }
 
-            if CHARS_ESCAPE_SEQUENCE_END.contains(&(next as char)) {
-                self.mode = Mode::Idle;
-            }
+        self.is_closed = true;
+        self.events.push_back(Event::Closed);
 
-            if self.is_echoing {
-                Ok(Some(Box::new([next])))
-            } else {
-                Ok(None)
-            }
-        } else {
-            if !CHARS_ESCAPE_SEQUENCE_END.contains(&(next as char)) {
-                return Ok(None);
-            }
+        /* The client has to be able to decompress everything up to here */
+        #[cfg(feature = "mccp")]
+        {
+            self.is_compression_starting = false;
+            self.is_compression_ending = self.compressor.is_some();
+        }
 
-            self.mode = Mode::Idle;
+        self.flush_carriage_return();
+        self.take_outgoing()
+    }
 
-            Ok(Some(Box::new([BEL])))
-        }
+    /// Returns whether the session has been closed via [`State::close`]
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
     }
 
-    /// Erases the current line from given text buffer. According to
-    /// [RFC-854](https://www.rfc-editor.org/rfc/rfc854#page-13), the last
-    /// CR LF should be kept.
+    /// Returns the character set that both sides have agreed on via CHARSET,
+    /// if any. Unless [`StateConfig::codec`] is set, the [`Codec`] follows the
+    /// agreed character set.
     ///
-    /// # Arguments
+    /// # Examples
     ///
-    /// * `buffer` - Text buffer that should be updated. All current line
-    ///   characters will be removed from the [`Vec`].
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State};
     ///
-    /// # Examples
+    /// let mut state = State::new(&StateConfig::default());
     ///
-    /// ```ignore
-    /// use telnet_server::telnet::State;
+    /// // IAC WILL CHARSET, answered with our supported character sets
+    /// state.write(&[255, 251, option::CHARSET])?;
     ///
-    /// let mut buffer = vec![b'a', b'b', b'c', b'\r', b'\n', b'd', b'e', b'f'];
-    /// State::erase_current_line(&mut buffer);
-    /// assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    /// // IAC SB CHARSET ACCEPTED "UTF-8" IAC SE
+    /// state.write(&[255, 250, option::CHARSET, 2])?;
+    /// state.write(b"UTF-8")?;
+    /// state.write(&[255, 240])?;
     ///
-    /// State::erase_current_line(&mut buffer);
-    /// assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    /// assert_eq!(state.charset(), Some("UTF-8"));
     ///
-    /// let mut buffer = vec![b'a', b'b', b'c', b'd', b'e', b'f'];
-    /// State::erase_current_line(&mut buffer);
-    /// assert!(buffer.is_empty());
+    /// Ok::<(), telnet_server::telnet::Error>(())
     /// ```
-    fn erase_current_line(buffer: &mut Vec<u8>) {
-        loop {
-            let buffer_len = buffer.len();
-
-            /* Remove all chars until \r\n reached */
-            if buffer_len < 2 {
-                buffer.clear();
-                break;
-            }
+    pub fn charset(&self) -> Option<&str> {
+        self.charset.charset()
+    }
 
-            let start_index = buffer_len - 2;
-            if contains_sequence(&buffer[start_index..], &CHARS_LINE_BREAK) {
-                break;
-            }
+    /// Returns the [`Codec`] that translates between Unicode text and the
+    /// character set of the client
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    /// assert_eq!(state.codec().name(), "UTF-8");
+    ///
+    /// // IAC WILL CHARSET, answered with our supported character sets
+    /// state.write(&[255, 251, option::CHARSET])?;
+    ///
+    /// // IAC SB CHARSET ACCEPTED "IBM437" IAC SE
+    /// state.write(&[255, 250, option::CHARSET, 2])?;
+    /// state.write(b"IBM437")?;
+    /// state.write(&[255, 240])?;
+    ///
+    /// assert_eq!(state.codec().encode("═"), [0xCD]);
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn codec(&self) -> Arc<dyn Codec> {
+        self.codec.clone()
+    }
 
-            buffer.pop();
-        }
+    /// Sets the [`Codec`] that translates between Unicode text and the
+    /// character set of the client. From now on, it is no longer changed by
+    /// CHARSET or the terminal type.
+    pub fn set_codec(&mut self, codec: Arc<dyn Codec>) {
+        self.codec = codec;
+        self.is_codec_fixed = true;
     }
-}
 
-impl Read for State {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    /// Moves handled, non-command data that has been received into `buf`
+    ///
+    /// # Returns
+    ///
+    /// Number of bytes written into `buf`, which is 0 if there's no data
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    /// state.write(b"Hello\r\n")?;
+    ///
+    /// let mut buf = [0; 16];
+    /// let length = state.read_data(&mut buf);
+    /// assert_eq!(&buf[..length], b"Hello\n");
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn read_data(&mut self, buf: &mut [u8]) -> usize {
         let limit = min(buf.len(), self.output_buffer.len());
 
         let (left, _) = buf.split_at_mut(limit);
         left.copy_from_slice(&self.output_buffer[..limit]);
         self.output_buffer.drain(..limit);
 
-        Ok(limit)
+        limit
     }
-}
 
-mod tests {
-    #[allow(unused_imports)]
-    use super::*;
+    /// Moves the next complete line of handled, non-command data out of the
+    /// state. An incomplete line stays in the state until its line break has
+    /// been received.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Vec<u8>)` with the line, ending with `\n`
+    /// * `None` if no complete line has been received yet
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    /// state.write(b"Hello\r\nWor")?;
+    ///
+    /// assert_eq!(state.read_line().as_deref(), Some(&b"Hello\n"[..]));
+    /// assert_eq!(state.read_line(), None);
+    ///
+    /// state.write(b"ld\r\n")?;
+    /// assert_eq!(state.read_line().as_deref(), Some(&b"World\n"[..]));
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn read_line(&mut self) -> Option<Vec<u8>> {
+        let end = self.output_buffer.iter().position(|&b| b == b'\n')? + 1;
+        Some(self.output_buffer.drain(..end).collect())
+    }
 
-    #[test]
-    fn erase_current_line_should_work() {
-        let mut buffer = vec![b'a', b'b', b'c', b'\r', b'\n', b'd', b'e', b'f'];
-        State::erase_current_line(&mut buffer);
-        /* RFC-854: 'The recipient should delete characters from the data stream
-         * back to, but not including, the last "CR LF" sequence sent over the
-         * TELNET connection.' */
-        assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    /// Returns the next received [`Event`] that hasn't been polled yet
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{Event, Signal, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC EOF
+    /// state.write(&[255, 236])?;
+    ///
+    /// assert_eq!(state.poll_event(), Some(Event::Signal(Signal::EndOfFile)));
+    /// assert_eq!(state.poll_event(), None);
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
 
-        State::erase_current_line(&mut buffer);
-        assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    /// Returns the currently active LINEMODE mode, if LINEMODE is enabled. See
+    /// [`super::linemode`] for the meaning of the bits.
+    pub fn linemode(&self) -> Option<u8> {
+        if self.is_remote_enabled(option::LINEMODE) {
+            Some(self.linemode.mode())
+        } else {
+            None
+        }
+    }
 
-        let mut buffer = vec![b'a', b'b', b'c', b'd', b'e', b'f'];
-        State::erase_current_line(&mut buffer);
-        assert!(buffer.is_empty());
+    /// Returns whether LINEMODE is enabled and the client forwards its
+    /// buffered data based on the configured
+    /// [`LinemodeConfig::forward_mask`]
+    pub fn is_linemode_forward_mask_active(&self) -> bool {
+        self.is_remote_enabled(option::LINEMODE) && self.linemode.is_forward_mask_active()
+    }
+
+    /// Returns the character that the client uses for given LINEMODE SLC
+    /// `function` (e.g. [`super::linemode::SLC_IP`]), if LINEMODE is enabled
+    /// and the function is supported by the client.
+    pub fn linemode_character(&self, function: u8) -> Option<u8> {
+        if self.is_remote_enabled(option::LINEMODE) {
+            self.linemode.character(function)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the last size of the terminal window that has been sent by the
+    /// client, if NAWS is enabled. Changes are also announced via
+    /// [`Event::WindowSize`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State, WindowSize};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC WILL NAWS, IAC SB NAWS 0 80 0 24 IAC SE
+    /// state.write(&[255, 251, option::NAWS])?;
+    /// state.write(&[255, 250, option::NAWS, 0, 80, 0, 24, 255, 240])?;
+    ///
+    /// assert_eq!(state.window_size(), Some(WindowSize { width: 80, height: 24 }));
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn window_size(&self) -> Option<WindowSize> {
+        self.window_size
+    }
+
+    /// Returns every terminal type that the client has sent so far, if
+    /// TERMINAL-TYPE is enabled. Once the client has sent all of them,
+    /// [`Event::TerminalTypes`] is announced.
+    ///
+    /// MTTS capabilities of MUD clients can be obtained via
+    /// [`super::terminal_type::mtts`].
+    pub fn terminal_types(&self) -> &[String] {
+        self.terminal_type.terminal_types()
+    }
+
+    /// Returns every environment variable that the client has sent so far, if
+    /// NEW-ENVIRON is enabled. Changes are announced via
+    /// [`Event::Environment`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC WILL NEW-ENVIRON, IAC SB NEW-ENVIRON IS VAR "USER" VALUE "laika" IAC SE
+    /// state.write(&[255, 251, option::NEW_ENVIRON])?;
+    /// state.write(&[255, 250, option::NEW_ENVIRON, 0, 0])?;
+    /// state.write(b"USER\x01laika")?;
+    /// state.write(&[255, 240])?;
+    ///
+    /// assert_eq!(state.environment()["USER"], "laika");
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn environment(&self) -> &BTreeMap<String, String> {
+        self.environment.variables()
+    }
+
+    /// Requests specific environment variables from the client. All variables
+    /// are already requested once the client enables NEW-ENVIRON, so this is
+    /// only necessary to get the current value of variables that may have
+    /// changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `variables` - [`Variable`]s that should be requested
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if NEW-ENVIRON is disabled
+    pub fn request_environment(&mut self, variables: &[Variable]) -> Option<Bytes> {
+        if !self.is_remote_enabled(option::NEW_ENVIRON) {
+            return None;
+        }
+
+        Decoder::write_sub_negotiation(
+            option::NEW_ENVIRON,
+            &Environment::request(variables),
+            &mut self.outgoing,
+        );
+        self.take_outgoing()
+    }
+
+    /// Requests the view of the client on the enabled options. Its answer is
+    /// announced via [`Event::Status`]. Requires STATUS to be enabled on the
+    /// other side.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if STATUS is disabled
+    pub fn request_status(&mut self) -> Option<Bytes> {
+        if !self.is_remote_enabled(option::STATUS) {
+            return None;
+        }
+
+        Decoder::write_sub_negotiation(option::STATUS, &[status::SEND], &mut self.outgoing);
+        self.take_outgoing()
+    }
+
+    /// Encodes an MSDP variable to be sent to the client. Requires MSDP to be
+    /// enabled on our side.
+    ///
+    /// # Arguments
+    ///
+    /// * `variable` - Name of the variable, e.g. `HEALTH`
+    /// * `value` - [`MsdpValue`] of the variable
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if MSDP is disabled
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use telnet_server::telnet::{option, MsdpValue, StateConfig, State};
+    ///
+    /// let mut state = State::new(&StateConfig::default());
+    ///
+    /// // IAC DO MSDP
+    /// state.write(&[255, 253, option::MSDP])?;
+    ///
+    /// let health = MsdpValue::String("100".to_string());
+    /// let data = state.encode_msdp("HEALTH", &health);
+    /// assert_eq!(data.as_deref(), Some(&b"\xff\xfa\x45\x01HEALTH\x02100\xff\xf0"[..]));
+    ///
+    /// Ok::<(), telnet_server::telnet::Error>(())
+    /// ```
+    pub fn encode_msdp(&mut self, variable: &str, value: &MsdpValue) -> Option<Bytes> {
+        if !self.is_local_enabled(option::MSDP) {
+            return None;
+        }
+
+        Decoder::write_sub_negotiation(
+            option::MSDP,
+            &msdp::encode(variable, value),
+            &mut self.outgoing,
+        );
+        self.take_outgoing()
+    }
+
+    /// Encodes a GMCP message to be sent to the client. Requires GMCP to be
+    /// enabled on our side.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if GMCP is disabled
+    #[cfg(feature = "gmcp")]
+    pub fn encode_gmcp(&mut self, message: &GmcpMessage) -> Option<Bytes> {
+        if !self.is_local_enabled(option::GMCP) {
+            return None;
+        }
+
+        Decoder::write_sub_negotiation(option::GMCP, &message.encode(), &mut self.outgoing);
+        self.take_outgoing()
+    }
+
+    /// Handles incoming data, decompressing it first while MCCP3 is active
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - Incoming TELNET data
+    /// * `response` - Buffer that data which has to be written back is added to
+    fn handle(&mut self, buf: &[u8], response: &mut Vec<u8>) -> Result<(), Error> {
+        #[cfg(feature = "mccp")]
+        if let Some(decompressor) = &mut self.decompressor {
+            let (data, end) = decompressor.decompress(buf)?;
+            self.handle_plain(&data, response)?;
+
+            if let Some(consumed) = end {
+                /* The client has ended the stream, the rest is uncompressed */
+                self.decompressor = None;
+                return self.handle(&buf[consumed..], response);
+            }
+
+            return Ok(());
+        }
+
+        self.handle_plain(buf, response)
+    }
+
+    /// Handles incoming uncompressed data event by event, see [`Decoder`]
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - Incoming TELNET data
+    /// * `response` - Buffer that data which has to be written back is added to
+    fn handle_plain(&mut self, buf: &[u8], response: &mut Vec<u8>) -> Result<(), Error> {
+        let mut position = 0;
+
+        /* Option handlers take precedence over the supported options */
+        while let Some(event) = self.decoder.next_event(
+            buf,
+            &mut position,
+            &mut self.outgoing,
+            &mut |side, option| {
+                self.option_handlers
+                    .iter_mut()
+                    .find(|h| h.option() == option)
+                    .map(|h| h.accept(side))
+            },
+        ) {
+            self.receive(event)?;
+            self.flush_outgoing(response);
+
+            #[cfg(feature = "mccp")]
+            if self.is_decompression_starting {
+                /* Everything after the sub negotiation is compressed */
+                self.is_decompression_starting = false;
+                self.decompressor = Some(Decompressor::new());
+
+                return self.handle(&buf[position..], response);
+            }
+        }
+
+        /* Replies to refused negotiations don't come with an event */
+        self.flush_outgoing(response);
+
+        Ok(())
+    }
+
+    /// Handles a single [`DecoderEvent`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Everythings okay
+    /// * `Err` - Data could not be interpreted
+    fn receive(&mut self, event: DecoderEvent) -> Result<(), Error> {
+        match event {
+            DecoderEvent::Data(data) => self.receive_data(data),
+            DecoderEvent::Command(command) => self.receive_command(command)?,
+            DecoderEvent::OptionEnabled(side, option) => self.on_option_enabled(side, option),
+            DecoderEvent::OptionDisabled(side, option) => self.on_option_disabled(side, option),
+            DecoderEvent::Subnegotiation {
+                option,
+                data,
+                is_last,
+            } => self.receive_sub_negotiation(option, data, is_last),
+            DecoderEvent::WindowSize(window_size) => self.set_window_size(window_size),
+            DecoderEvent::TimingMark => self.timing_marks_answered += 1,
+        }
+
+        Ok(())
+    }
+
+    /// Handles incoming non-command data byte by byte. Options can't change
+    /// within the data, so they're only looked up once.
+    fn receive_data(&mut self, data: &[u8]) {
+        let is_binary = self.is_remote_enabled(option::BINARY);
+        let is_echoing = self.is_echoing();
+
+        for &next in data {
+            match self.mode {
+                Mode::Idle if is_binary => self.next_on_binary(next, is_echoing),
+                Mode::Idle => self.next_on_idle(next, is_echoing),
+                Mode::AnsiEscapeSequence => self.next_as_escape_sequence(next, is_echoing),
+            }
+        }
+    }
+
+    /// Encodes outgoing application data as NVT data, see [`State::encode`]
+    fn encode_data(&mut self, data: &[u8]) {
+        let is_binary = self.is_local_enabled(option::BINARY);
+        let encoded = &mut self.outgoing;
+        encoded.reserve(data.len());
+
+        for &byte in data {
+            if self.is_encoding_carriage_return {
+                self.is_encoding_carriage_return = false;
+                encoded.push(CHAR_CARRIAGE_RETURN);
+
+                if byte == CHAR_LINE_FEED {
+                    encoded.push(CHAR_LINE_FEED);
+                    continue;
+                }
+
+                encoded.push(CHAR_NUL);
+            }
+
+            match byte {
+                IAC => encoded.extend_from_slice(&[IAC, IAC]),
+                CHAR_CARRIAGE_RETURN if !is_binary => self.is_encoding_carriage_return = true,
+                CHAR_LINE_FEED if !is_binary => {
+                    encoded.extend_from_slice(&[CHAR_CARRIAGE_RETURN, CHAR_LINE_FEED])
+                }
+                _ => encoded.push(byte),
+            }
+        }
+    }
+
+    /// Adds a CR that has been held back by [`State::encode_data`], if any, to
+    /// the outgoing data
+    fn flush_carriage_return(&mut self) {
+        if !self.is_encoding_carriage_return {
+            return;
+        }
+
+        self.is_encoding_carriage_return = false;
+        self.outgoing
+            .extend_from_slice(&[CHAR_CARRIAGE_RETURN, CHAR_NUL]);
+    }
+
+    /// Handles incoming `next` data byte when [`State`] is in idle mode
+    fn next_on_idle(&mut self, next: u8, is_echoing: bool) {
+        if self.is_receiving_carriage_return {
+            self.is_receiving_carriage_return = false;
+
+            if next == CHAR_LINE_FEED || next == CHAR_NUL {
+                /* Second part of the line break that has already been handled */
+                return;
+            }
+        }
+
+        match next {
+            CHAR_CARRIAGE_RETURN => {
+                self.is_receiving_carriage_return = true;
+                self.line_break();
+            }
+            CHAR_LINE_FEED => self.line_break(),
+            CHAR_DELETE | CHAR_BACK_SPACE => self.erase_character(),
+            CHAR_ESCAPE => {
+                self.mode = Mode::AnsiEscapeSequence;
+
+                if is_echoing {
+                    self.echo(next);
+                    return;
+                }
+
+                if self.handle_ansi_escape_sequences {
+                    self.output_buffer.push(next);
+                }
+            }
+            _ => {
+                self.output_buffer.push(next);
+
+                if is_echoing {
+                    self.echo(next);
+                }
+            }
+        }
+    }
+
+    /// Handles incoming `next` data byte when [`State`] is in idle mode and
+    /// the other side transmits binary data. Every byte is passed through
+    /// untouched.
+    fn next_on_binary(&mut self, next: u8, is_echoing: bool) {
+        self.is_receiving_carriage_return = false;
+        self.output_buffer.push(next);
+
+        if is_echoing {
+            self.echo(next);
+        }
+    }
+
+    /// Handles incoming `next` data byte when [`State`] is in idle mode and in
+    /// an ANSI escape sequence
+    fn next_as_escape_sequence(&mut self, next: u8, is_echoing: bool) {
+        if self.handle_ansi_escape_sequences {
+            self.output_buffer.push(next);
+
+            if CHARS_ESCAPE_SEQUENCE_END.contains(&(next as char)) {
+                self.mode = Mode::Idle;
+            }
+
+            if is_echoing {
+                self.echo(next);
+            }
+        } else if CHARS_ESCAPE_SEQUENCE_END.contains(&(next as char)) {
+            self.mode = Mode::Idle;
+            self.outgoing.push(BEL);
+        }
+    }
+
+    /// Handles an incoming command that isn't handled by the [`Decoder`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Everythings okay
+    /// * `Err` - Command is unknown
+    fn receive_command(&mut self, command: u8) -> Result<(), Error> {
+        match command {
+            IAC_NO_OPERATION | IAC_DATA_MARK | IAC_GO_AHEAD | IAC_END_OF_RECORD => {
+                /* Nothing to do. The urgent part of a Synch can't be detected
+                 * on our side, so a data mark is ignored as well. */
+            }
+            IAC_BREAK => self.signal(Signal::Break),
+            IAC_INTERRUPT_PROCESS => self.signal(Signal::InterruptProcess),
+            IAC_ABORT_OUTPUT => self.signal(Signal::AbortOutput),
+            IAC_ARE_YOU_THERE => self.outgoing.extend_from_slice(ARE_YOU_THERE_RESPONSE),
+            IAC_ERASE_CHARACTER => self.erase_character(),
+            IAC_ERASE_LINE => self.erase_line(),
+            IAC_END_OF_FILE => self.signal(Signal::EndOfFile),
+            IAC_SUSPEND => self.signal(Signal::Suspend),
+            IAC_ABORT => self.signal(Signal::Abort),
+            _ => return Err(Error::UnknownCommand(command)),
+        };
+
+        Ok(())
+    }
+
+    /// Adds a part of the payload of a sub negotiation, handling the payload
+    /// once it's complete. Payloads that become too long are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option
+    /// * `data` - Part of the payload
+    /// * `is_last` - Whether this is the last part of the payload
+    fn receive_sub_negotiation(&mut self, option: u8, data: &[u8], is_last: bool) {
+        /* The option counts towards the length as well */
+        let length = 1 + self.sub_negotiation_buffer.len() + data.len();
+
+        if length > MAX_SUB_NEGOTIATION_LENGTH {
+            self.is_sub_negotiation_overflowing = true;
+            self.sub_negotiation_buffer.clear();
+        }
+
+        if !self.is_sub_negotiation_overflowing {
+            self.sub_negotiation_buffer.extend_from_slice(data);
+        }
+
+        if !is_last {
+            return;
+        }
+
+        if self.is_sub_negotiation_overflowing {
+            self.is_sub_negotiation_overflowing = false;
+            return;
+        }
+
+        let payload = core::mem::take(&mut self.sub_negotiation_buffer);
+        self.handle_sub_negotiation(option, &payload);
+
+        /* Keep the allocation for the next sub negotiation */
+        self.sub_negotiation_buffer = payload;
+        self.sub_negotiation_buffer.clear();
+    }
+
+    /// Returns the registered [`OptionHandler`] for `option`, if any
+    fn option_handler(&mut self, option: u8) -> Option<&mut Box<dyn OptionHandler>> {
+        self.option_handlers
+            .iter_mut()
+            .find(|h| h.option() == option)
+    }
+
+    /// Handles a complete sub negotiation of an enabled option
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option
+    /// * `data` - Un-escaped payload of the sub negotiation
+    fn handle_sub_negotiation(&mut self, option: u8, data: &[u8]) {
+        if let Some(handler) = self.option_handler(option) {
+            let payloads = handler.sub_negotiation(data);
+            self.write_sub_negotiations(option, &payloads);
+            return;
+        }
+
+        let payloads = match option {
+            option::LINEMODE => self.linemode.handle(data),
+            option::TERMINAL_TYPE => {
+                let was_complete = self.terminal_type.is_complete();
+                let payloads = self.terminal_type.handle(data);
+
+                if !was_complete && self.terminal_type.is_complete() {
+                    let terminal_types = self.terminal_type.terminal_types().to_vec();
+
+                    /* An agreed character set is more reliable than a guess */
+                    if self.charset.charset().is_none() {
+                        if let Some(codec) = codec::for_terminal_types(&terminal_types) {
+                            self.change_codec(codec);
+                        }
+                    }
+
+                    self.events.push_back(Event::TerminalTypes(terminal_types));
+                }
+
+                payloads
+            }
+            option::NEW_ENVIRON => {
+                if self.environment.handle(data) {
+                    let variables = self.environment.variables().clone();
+                    self.events.push_back(Event::Environment(variables));
+                }
+
+                vec![]
+            }
+            #[cfg(feature = "mccp")]
+            option::COMPRESS3 if self.is_local_enabled(option) => {
+                /* The client compresses everything after this sub negotiation */
+                self.is_decompression_starting = true;
+                vec![]
+            }
+            option::STATUS => match data {
+                [status::SEND] if self.is_local_enabled(option) => {
+                    let local = self.enabled_options(Side::Local);
+                    let remote = self.enabled_options(Side::Remote);
+                    vec![status::encode(&local, &remote)]
+                }
+                _ if self.is_remote_enabled(option) => {
+                    if let Some(report) = status::parse(data) {
+                        self.events.push_back(Event::Status(report));
+                    }
+
+                    vec![]
+                }
+                _ => vec![],
+            },
+            option::MSDP => {
+                for (variable, value) in msdp::parse(data) {
+                    self.events.push_back(Event::Msdp(variable, value));
+                }
+
+                vec![]
+            }
+            #[cfg(feature = "gmcp")]
+            option::GMCP => {
+                if let Some(message) = GmcpMessage::parse(data) {
+                    self.events.push_back(Event::Gmcp(message));
+                }
+
+                vec![]
+            }
+            option::CHARSET => {
+                let (payloads, is_agreed) = self.charset.handle(data);
+
+                if let Some(charset) = self.charset.charset().filter(|_| is_agreed) {
+                    let charset = charset.to_string();
+
+                    if let Some(codec) = codec::for_name(&charset) {
+                        self.change_codec(codec);
+                    }
+
+                    self.events.push_back(Event::Charset(charset));
+                }
+
+                payloads
+            }
+            _ => vec![],
+        };
+
+        self.write_sub_negotiations(option, &payloads);
+    }
+
+    /// Requests to disable `option` on given `side`, taking care of the option
+    /// if it has been disabled right away
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    fn request_disable(&mut self, side: Side, option: u8) -> Option<Bytes> {
+        let was_enabled = self.decoder.is_enabled(side, option);
+
+        match side {
+            Side::Local => self.decoder.disable_local(option, &mut self.outgoing),
+            Side::Remote => self.decoder.disable_remote(option, &mut self.outgoing),
+        }
+
+        if was_enabled && !self.decoder.is_enabled(side, option) {
+            self.on_option_disabled(side, option);
+        }
+
+        self.take_outgoing()
+    }
+
+    /// Sets up `option` after it has been enabled on given `side`
+    fn on_option_enabled(&mut self, side: Side, option: u8) {
+        if let Some(handler) = self.option_handler(option) {
+            let payloads = handler.enabled(side);
+            self.write_sub_negotiations(option, &payloads);
+            return;
+        }
+
+        let payloads = match (side, option) {
+            (Side::Remote, option::LINEMODE) => self.linemode.enable(),
+            (Side::Remote, option::TERMINAL_TYPE) => self.terminal_type.enable(),
+            (Side::Remote, option::NEW_ENVIRON) => self.environment.enable(),
+            (_, option::CHARSET) => self.charset.enable(),
+            (Side::Local, option::MSSP) => {
+                /* The status is sent once, right after the client asked */
+                let Some(variables) = self.mssp.as_ref().map(|provider| provider()) else {
+                    return;
+                };
+
+                vec![mssp::encode(&variables)]
+            }
+            #[cfg(feature = "mccp")]
+            (Side::Local, option::COMPRESS2) => {
+                /* Everything after this sub negotiation is compressed */
+                self.is_compression_starting = true;
+                vec![vec![]]
+            }
+            _ => vec![],
+        };
+
+        self.write_sub_negotiations(option, &payloads);
+    }
+
+    /// Resets `option` after it has been disabled on given `side`
+    fn on_option_disabled(&mut self, side: Side, option: u8) {
+        if let Some(handler) = self.option_handler(option) {
+            handler.disabled(side);
+            return;
+        }
+
+        match (side, option) {
+            (Side::Remote, option::LINEMODE) => self.linemode.disable(),
+            (Side::Remote, option::NAWS) => self.window_size = None,
+            (Side::Remote, option::TERMINAL_TYPE) => self.terminal_type.disable(),
+            (Side::Remote, option::NEW_ENVIRON) => self.environment.disable(),
+            #[cfg(feature = "mccp")]
+            (Side::Local, option::COMPRESS2) => {
+                self.is_compression_starting = false;
+                self.is_compression_ending = self.compressor.is_some();
+            }
+            /* CHARSET is usable as long as one side has it enabled */
+            (_, option::CHARSET)
+                if !self.is_local_enabled(option) && !self.is_remote_enabled(option) =>
+            {
+                self.charset.disable()
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves the outgoing data into `response`. While MCCP2 is active, the
+    /// data is compressed. Compression is started or ended afterwards, if
+    /// requested while producing the data.
+    #[cfg(feature = "mccp")]
+    fn flush_outgoing(&mut self, response: &mut Vec<u8>) {
+        match &mut self.compressor {
+            Some(compressor) if !self.outgoing.is_empty() => {
+                response.extend(compressor.compress(&self.outgoing));
+                self.outgoing.clear();
+            }
+            _ => response.append(&mut self.outgoing),
+        }
+
+        if self.is_compression_ending {
+            self.is_compression_ending = false;
+
+            if let Some(compressor) = self.compressor.take() {
+                response.extend(compressor.finish());
+            }
+        }
+
+        if self.is_compression_starting {
+            self.is_compression_starting = false;
+            self.compressor = Some(Compressor::new());
+        }
+    }
+
+    /// Moves the outgoing data into `response`. Without MCCP, there's nothing
+    /// else to do.
+    #[cfg(not(feature = "mccp"))]
+    fn flush_outgoing(&mut self, response: &mut Vec<u8>) {
+        response.append(&mut self.outgoing);
+    }
+
+    /// Takes the outgoing data, prepared to be sent to the other side like
+    /// [`State::flush_outgoing`]
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Bytes)` if data has to be sent to the other side
+    /// * `None` if nothing has to be sent
+    fn take_outgoing(&mut self) -> Option<Bytes> {
+        let mut output = vec![];
+        self.flush_outgoing(&mut output);
+
+        if output.is_empty() {
+            None
+        } else {
+            Some(output.into_boxed_slice())
+        }
+    }
+
+    /// Replaces the [`Codec`], unless it has been set explicitly
+    fn change_codec(&mut self, codec: Arc<dyn Codec>) {
+        if !self.is_codec_fixed {
+            self.codec = codec;
+        }
+    }
+
+    /// Updates the size of the terminal window, queueing an [`Event`] if it
+    /// has changed
+    fn set_window_size(&mut self, window_size: WindowSize) {
+        if self.window_size == Some(window_size) {
+            return;
+        }
+
+        self.window_size = Some(window_size);
+        self.events.push_back(Event::WindowSize(window_size));
+    }
+
+    /// Removes the last character of the current line. Depending on the
+    /// [`Codec`], a character may span several bytes.
+    fn erase_character(&mut self) {
+        if self
+            .output_buffer
+            .ends_with(self.line_terminator.as_bytes())
+        {
+            /* Only characters of the current line can be erased */
+            return;
+        }
+
+        /* Remove continuation bytes until the first byte of the character has
+         * been removed as well */
+        let mut is_removed = false;
+        while let Some(byte) = self.output_buffer.pop() {
+            is_removed = true;
+
+            if !self.codec.is_continuation(byte) {
+                break;
+            }
+        }
+
+        /* Nothing to erase on the screen of the client either */
+        if is_removed && self.is_echoing() {
+            /* Send fake backspace on echo mode */
+            self.outgoing
+                .extend_from_slice(&[CHAR_BACK_SPACE, b' ', CHAR_BACK_SPACE]);
+        }
+    }
+
+    /// Removes the whole current line
+    fn erase_line(&mut self) {
+        Self::erase_current_line(&mut self.output_buffer, self.line_terminator.as_bytes());
+
+        if self.is_echoing() {
+            self.outgoing.extend_from_slice(&ANSI_SEQUENCE_ERASE_LINE);
+        }
+    }
+
+    /// Adds a line break to the readable data
+    fn line_break(&mut self) {
+        self.output_buffer
+            .extend_from_slice(self.line_terminator.as_bytes());
+
+        if self.is_echoing() {
+            self.outgoing
+                .extend_from_slice(&[CHAR_CARRIAGE_RETURN, CHAR_LINE_FEED]);
+        }
+    }
+
+    /// Echoes a received data byte, escaping an IAC
+    fn echo(&mut self, byte: u8) {
+        if byte == IAC {
+            self.outgoing.push(IAC);
+        }
+
+        self.outgoing.push(byte);
+    }
+
+    /// Queues a received [`Signal`] as [`Event`]
+    fn signal(&mut self, signal: Signal) {
+        self.events.push_back(Event::Signal(signal));
+    }
+
+    /// Returns whether every incoming, non-command char should be echoed back
+    /// to the connection
+    fn is_echoing(&self) -> bool {
+        self.is_local_enabled(option::ECHO)
+    }
+
+    /// Adds sub negotiations for every given payload to the outgoing data
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - Code of the option
+    /// * `payloads` - Payloads of the sub negotiations, without the option
+    fn write_sub_negotiations(&mut self, option: u8, payloads: &[Vec<u8>]) {
+        for payload in payloads {
+            Decoder::write_sub_negotiation(option, payload, &mut self.outgoing);
+        }
+    }
+
+    /// Erases the current line from given text buffer. According to
+    /// [RFC-854](https://www.rfc-editor.org/rfc/rfc854#page-13), the last
+    /// CR LF (or whatever line break is used) should be kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Text buffer that should be updated. All current line
+    ///   characters will be removed from the [`Vec`].
+    /// * `line_break` - Sequence that separates lines in `buffer`
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use telnet_server::telnet::State;
+    ///
+    /// let mut buffer = vec![b'a', b'b', b'c', b'\r', b'\n', b'd', b'e', b'f'];
+    /// State::erase_current_line(&mut buffer, b"\r\n");
+    /// assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    ///
+    /// State::erase_current_line(&mut buffer, b"\r\n");
+    /// assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+    ///
+    /// let mut buffer = vec![b'a', b'b', b'c', b'd', b'e', b'f'];
+    /// State::erase_current_line(&mut buffer, b"\r\n");
+    /// assert!(buffer.is_empty());
+    /// ```
+    fn erase_current_line(buffer: &mut Vec<u8>, line_break: &[u8]) {
+        loop {
+            let buffer_len = buffer.len();
+
+            /* Remove all chars until line break reached */
+            if buffer_len < line_break.len() {
+                buffer.clear();
+                break;
+            }
+
+            let start_index = buffer_len - line_break.len();
+            if contains_sequence(&buffer[start_index..], line_break) {
+                break;
+            }
+
+            buffer.pop();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for State {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.read_data(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decoder::{
+        IAC_DO, IAC_DONT, IAC_SUBNEGOTIATION_END, IAC_SUBNEGOTIATION_START, IAC_WILL, IAC_WONT,
+    };
+    use super::super::linemode::{MODE, MODE_ACK, MODE_EDIT, MODE_TRAPSIG};
+    use super::*;
+
+    #[test]
+    fn erase_current_line_should_work() {
+        let mut buffer = vec![b'a', b'b', b'c', b'\r', b'\n', b'd', b'e', b'f'];
+        State::erase_current_line(&mut buffer, b"\r\n");
+        /* RFC-854: 'The recipient should delete characters from the data stream
+         * back to, but not including, the last "CR LF" sequence sent over the
+         * TELNET connection.' */
+        assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+
+        State::erase_current_line(&mut buffer, b"\r\n");
+        assert_eq!(buffer, [b'a', b'b', b'c', b'\r', b'\n']);
+
+        let mut buffer = vec![b'a', b'b', b'c', b'd', b'e', b'f'];
+        State::erase_current_line(&mut buffer, b"\r\n");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn negotiates_supported_option() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_DO, option::ECHO]).unwrap();
+        assert_eq!(result.as_deref(), Some(&[IAC, IAC_WILL, option::ECHO][..]));
+        assert!(state.is_local_enabled(option::ECHO));
+
+        /* Must not be acknowledged again as nothing has changed */
+        let result = state.write(&[IAC, IAC_DO, option::ECHO]).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn refuses_unsupported_option() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_WILL, option::ECHO]).unwrap();
+        assert_eq!(result.as_deref(), Some(&[IAC, IAC_DONT, option::ECHO][..]));
+        assert!(!state.is_remote_enabled(option::ECHO));
+    }
+
+    #[test]
+    fn ignores_disabling_disabled_option() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_DONT, option::ECHO]).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn does_not_answer_acknowledgement_of_own_request() {
+        let mut state = State::new(&StateConfig::default());
+
+        assert!(state.enable_local(option::ECHO).is_some());
+        assert!(state.is_local_pending(option::ECHO));
+
+        let result = state.write(&[IAC, IAC_DO, option::ECHO]).unwrap();
+        assert!(result.is_none());
+        assert!(state.is_local_enabled(option::ECHO));
+        assert!(!state.is_local_pending(option::ECHO));
+    }
+
+    #[test]
+    fn sets_up_linemode_once_enabled() {
+        let mut state = State::new(&StateConfig {
+            linemode: Some(LinemodeConfig {
+                mode: MODE_EDIT | MODE_TRAPSIG,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let result = state.write(&[IAC, IAC_WILL, option::LINEMODE]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(
+                &[
+                    IAC,
+                    IAC_DO,
+                    option::LINEMODE,
+                    IAC,
+                    IAC_SUBNEGOTIATION_START,
+                    option::LINEMODE,
+                    MODE,
+                    MODE_EDIT | MODE_TRAPSIG,
+                    IAC,
+                    IAC_SUBNEGOTIATION_END
+                ][..]
+            )
+        );
+
+        let result = state
+            .write(&[
+                IAC,
+                IAC_SUBNEGOTIATION_START,
+                option::LINEMODE,
+                MODE,
+                MODE_EDIT | MODE_TRAPSIG | MODE_ACK,
+                IAC,
+                IAC_SUBNEGOTIATION_END,
+            ])
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(state.linemode(), Some(MODE_EDIT | MODE_TRAPSIG));
+    }
+
+    #[test]
+    fn refuses_linemode_by_default() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_WILL, option::LINEMODE]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[IAC, IAC_DONT, option::LINEMODE][..])
+        );
+        assert_eq!(state.linemode(), None);
+    }
+
+    #[test]
+    fn unescapes_iac_in_sub_negotiation() {
+        let mut state = State::new(&StateConfig {
+            linemode: Some(LinemodeConfig::default()),
+            ..Default::default()
+        });
+        state.write(&[IAC, IAC_WILL, option::LINEMODE]).unwrap();
+
+        /* SLC IP VALUE 255 */
+        let data = [
+            IAC,
+            IAC_SUBNEGOTIATION_START,
+            option::LINEMODE,
+            3,
+            3,
+            2,
+            IAC,
+            IAC,
+            IAC,
+            IAC_SUBNEGOTIATION_END,
+        ];
+        let result = state.write(&data).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(
+                &[
+                    IAC,
+                    IAC_SUBNEGOTIATION_START,
+                    option::LINEMODE,
+                    3,
+                    3,
+                    130,
+                    IAC,
+                    IAC,
+                    IAC,
+                    IAC_SUBNEGOTIATION_END
+                ][..]
+            )
+        );
+        assert_eq!(state.linemode_character(3), Some(IAC));
+
+        /* Data afterwards is readable again */
+        state.write(b"abc").unwrap();
+        let mut buf = [0; 3];
+        assert_eq!(state.read_data(&mut buf), 3);
+        assert_eq!(&buf, b"abc");
+    }
+
+    #[test]
+    fn queues_signals() {
+        let mut state = State::new(&StateConfig::default());
+
+        state
+            .write(&[IAC, IAC_SUSPEND, b'a', IAC, IAC_ABORT])
+            .unwrap();
+        assert_eq!(state.poll_event(), Some(Event::Signal(Signal::Suspend)));
+        assert_eq!(state.poll_event(), Some(Event::Signal(Signal::Abort)));
+        assert_eq!(state.poll_event(), None);
+    }
+
+    #[test]
+    fn announces_changed_window_size() {
+        let mut state = State::new(&StateConfig::default());
+        state.write(&[IAC, IAC_WILL, option::NAWS]).unwrap();
+
+        let naws = [
+            IAC,
+            IAC_SUBNEGOTIATION_START,
+            option::NAWS,
+            0,
+            80,
+            0,
+            24,
+            IAC,
+            IAC_SUBNEGOTIATION_END,
+        ];
+        state.write(&naws).unwrap();
+        state.write(&naws).unwrap();
+
+        let window_size = WindowSize {
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(state.window_size(), Some(window_size));
+        assert_eq!(state.poll_event(), Some(Event::WindowSize(window_size)));
+        /* Same size must not be announced twice */
+        assert_eq!(state.poll_event(), None);
+
+        state.write(&[IAC, IAC_WONT, option::NAWS]).unwrap();
+        assert_eq!(state.window_size(), None);
+    }
+
+    #[test]
+    fn requests_terminal_types_until_repeated() {
+        let mut state = State::new(&StateConfig::default());
+        let send = [
+            IAC,
+            IAC_SUBNEGOTIATION_START,
+            option::TERMINAL_TYPE,
+            1,
+            IAC,
+            IAC_SUBNEGOTIATION_END,
+        ];
+
+        let result = state
+            .write(&[IAC, IAC_WILL, option::TERMINAL_TYPE])
+            .unwrap();
+        assert_eq!(result.as_deref().map(|r| &r[3..]), Some(&send[..]));
+
+        for name in [&b"XTERM"[..], b"VT100", b"VT100"] {
+            let mut data = vec![IAC, IAC_SUBNEGOTIATION_START, option::TERMINAL_TYPE, 0];
+            data.extend_from_slice(name);
+            data.extend_from_slice(&[IAC, IAC_SUBNEGOTIATION_END]);
+
+            let result = state.write(&data).unwrap();
+            if name == b"XTERM" {
+                assert_eq!(result.as_deref(), Some(&send[..]));
+            }
+        }
+
+        let terminal_types = vec!["XTERM".to_string(), "VT100".to_string()];
+        assert_eq!(state.terminal_types(), terminal_types);
+        assert_eq!(
+            state.poll_event(),
+            Some(Event::TerminalTypes(terminal_types))
+        );
+    }
+
+    #[test]
+    fn handles_standard_commands() {
+        let mut state = State::new(&StateConfig::default());
+
+        let data = [
+            b'a',
+            IAC,
+            IAC_NO_OPERATION,
+            b'b',
+            IAC,
+            IAC_INTERRUPT_PROCESS,
+            IAC,
+            IAC_ERASE_CHARACTER,
+            IAC,
+            IAC,
+            IAC,
+            IAC_GO_AHEAD,
+        ];
+        assert!(state.write(&data).unwrap().is_none());
+
+        let mut buf = [0; 4];
+        assert_eq!(state.read_data(&mut buf), 2);
+        assert_eq!(buf[..2], [b'a', IAC]);
+        assert_eq!(
+            state.poll_event(),
+            Some(Event::Signal(Signal::InterruptProcess))
+        );
+    }
+
+    #[test]
+    fn answers_are_you_there() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_ARE_YOU_THERE]).unwrap();
+        assert_eq!(result.as_deref(), Some(ARE_YOU_THERE_RESPONSE));
+    }
+
+    #[test]
+    fn keeps_bare_erase_bytes_as_data() {
+        let mut state = State::new(&StateConfig::default());
+
+        state
+            .write(&[b'a', IAC_ERASE_CHARACTER, IAC_ERASE_LINE])
+            .unwrap();
+
+        let mut buf = [0; 3];
+        assert_eq!(state.read_data(&mut buf), 3);
+        assert_eq!(buf, [b'a', IAC_ERASE_CHARACTER, IAC_ERASE_LINE]);
+    }
+
+    #[test]
+    fn encodes_outgoing_data() {
+        let mut state = State::new(&StateConfig::default());
+
+        assert_eq!(&*state.encode(b"a\nb\r\nc\rd"), b"a\r\nb\r\nc\r\0d");
+        assert_eq!(&*state.encode(&[IAC]), &[IAC, IAC]);
+
+        /* CR LF split over multiple writes */
+        assert!(state.encode(b"\r").is_empty());
+        assert_eq!(&*state.encode(b"\n"), b"\r\n");
+        assert!(state.flush_encoding().is_none());
+    }
+
+    #[test]
+    fn encodes_binary_data() {
+        let mut state = State::new(&StateConfig::default());
+        state.enable_local(option::BINARY);
+        state.write(&[IAC, IAC_DO, option::BINARY]).unwrap();
+
+        assert_eq!(
+            &*state.encode(&[b'\r', b'\n', b'\r', IAC]),
+            &[b'\r', b'\n', b'\r', IAC, IAC]
+        );
+    }
+
+    #[test]
+    fn normalizes_line_breaks() {
+        let mut state = State::new(&StateConfig::default());
+
+        state.write(b"a\r\nb\r\0c\nd\re").unwrap();
+
+        let mut buf = [0; 16];
+        let read = state.read_data(&mut buf);
+        assert_eq!(&buf[..read], b"a\nb\nc\nd\ne");
+    }
+
+    #[test]
+    fn normalizes_line_breaks_split_over_writes() {
+        let mut state = State::new(&StateConfig {
+            line_terminator: LineTerminator::CrLf,
+            ..Default::default()
+        });
+
+        state.write(b"a\r").unwrap();
+        state.write(b"\nb\n").unwrap();
+
+        let mut buf = [0; 16];
+        let read = state.read_data(&mut buf);
+        assert_eq!(&buf[..read], b"a\r\nb\r\n");
+    }
+
+    #[test]
+    fn keeps_line_break_split_by_command() {
+        let mut state = State::new(&StateConfig::default());
+
+        state.write(b"a\r").unwrap();
+        state.write(&[IAC, IAC_NO_OPERATION, b'\n', b'b']).unwrap();
+        state.write(&[b'\r', IAC, IAC, b'\n']).unwrap();
+
+        let mut buf = [0; 16];
+        let read = state.read_data(&mut buf);
+        assert_eq!(&buf[..read], b"a\nb\n\xff\n");
+    }
+
+    #[test]
+    fn does_not_erase_previous_line() {
+        let mut state = State::new(&StateConfig::default());
+
+        state.write(b"a\r\n").unwrap();
+        state.write(&[CHAR_DELETE, b'b', CHAR_DELETE]).unwrap();
+
+        let mut buf = [0; 16];
+        let read = state.read_data(&mut buf);
+        assert_eq!(&buf[..read], b"a\n");
+    }
+
+    #[test]
+    fn echoes_erase_only_if_something_is_erased() {
+        let mut state = State::new(&StateConfig::default());
+        state.write(&[IAC, IAC_DO, option::ECHO]).unwrap();
+
+        /* Empty line, e.g. right after the prompt */
+        let result = state.write(&[CHAR_DELETE]).unwrap();
+        assert!(result.is_none());
+
+        let result = state.write(&[b'a', CHAR_DELETE]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[b'a', CHAR_BACK_SPACE, b' ', CHAR_BACK_SPACE][..])
+        );
+
+        /* Line has just been terminated */
+        state.write(b"b\r\n").unwrap();
+        let result = state.write(&[IAC, IAC_ERASE_CHARACTER]).unwrap();
+        assert!(result.is_none());
+    }
+
+    /// Handler that counts the received sub negotiations of option 200
+    struct CountingHandler(u8);
+
+    impl OptionHandler for CountingHandler {
+        fn option(&self) -> u8 {
+            200
+        }
+
+        fn accept(&mut self, side: Side) -> bool {
+            side == Side::Local
+        }
+
+        fn enabled(&mut self, _side: Side) -> Vec<Vec<u8>> {
+            vec![vec![0]]
+        }
+
+        fn sub_negotiation(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+            self.0 += 1;
+            vec![vec![self.0, data.len() as u8]]
+        }
+    }
+
+    #[test]
+    fn delegates_to_option_handler() {
+        let mut state = State::new(&StateConfig {
+            option_handlers: vec![Arc::new(|| Box::new(CountingHandler(0)))],
+            ..Default::default()
+        });
+
+        let result = state.write(&[IAC, IAC_WILL, 200]).unwrap();
+        assert_eq!(result.as_deref(), Some(&[IAC, IAC_DONT, 200][..]));
+
+        let result = state.write(&[IAC, IAC_DO, 200]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(
+                &[
+                    IAC,
+                    IAC_WILL,
+                    200,
+                    IAC,
+                    IAC_SUBNEGOTIATION_START,
+                    200,
+                    0,
+                    IAC,
+                    IAC_SUBNEGOTIATION_END
+                ][..]
+            )
+        );
+
+        let result = state
+            .write(&[
+                IAC,
+                IAC_SUBNEGOTIATION_START,
+                200,
+                1,
+                IAC,
+                IAC,
+                2,
+                IAC,
+                IAC_SUBNEGOTIATION_END,
+            ])
+            .unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(
+                &[
+                    IAC,
+                    IAC_SUBNEGOTIATION_START,
+                    200,
+                    1,
+                    3,
+                    IAC,
+                    IAC_SUBNEGOTIATION_END
+                ][..]
+            )
+        );
+    }
+
+    #[test]
+    fn discards_too_long_sub_negotiation() {
+        let mut state = State::new(&StateConfig {
+            option_handlers: vec![Arc::new(|| Box::new(CountingHandler(0)))],
+            ..Default::default()
+        });
+        state.write(&[IAC, IAC_DO, 200]).unwrap();
+
+        state.write(&[IAC, IAC_SUBNEGOTIATION_START, 200]).unwrap();
+        state.write(&vec![1; MAX_SUB_NEGOTIATION_LENGTH]).unwrap();
+        let result = state.write(&[IAC, IAC_SUBNEGOTIATION_END, b'a']).unwrap();
+        assert!(result.is_none());
+
+        /* Regular data afterwards is still handled */
+        let mut buf = [0; 4];
+        assert_eq!(state.read_data(&mut buf), 1);
+        assert_eq!(buf[0], b'a');
+    }
+
+    #[test]
+    fn passes_binary_data_through() {
+        let mut state = State::new(&StateConfig {
+            accept_binary: true,
+            ..Default::default()
+        });
+
+        let result = state.write(&[IAC, IAC_WILL, option::BINARY]).unwrap();
+        assert_eq!(result.as_deref(), Some(&[IAC, IAC_DO, option::BINARY][..]));
+
+        let data = [
+            b'\r',
+            0,
+            CHAR_ESCAPE,
+            b'[',
+            b'A',
+            CHAR_DELETE,
+            IAC,
+            IAC,
+            b'\r',
+            b'\n',
+        ];
+        assert!(state.write(&data).unwrap().is_none());
+
+        let mut buf = [0; 16];
+        let read = state.read_data(&mut buf);
+        assert_eq!(
+            &buf[..read],
+            &[
+                b'\r',
+                0,
+                CHAR_ESCAPE,
+                b'[',
+                b'A',
+                CHAR_DELETE,
+                IAC,
+                b'\r',
+                b'\n'
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_binary_by_default() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_DO, option::BINARY]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[IAC, IAC_WONT, option::BINARY][..])
+        );
+    }
+
+    #[test]
+    fn marks_end_of_prompt() {
+        let mut state = State::new(&StateConfig::default());
+        assert_eq!(&*state.encode_prompt(b">"), &[b'>', IAC, IAC_GO_AHEAD]);
+
+        state
+            .write(&[IAC, IAC_DO, option::SUPPRESS_GO_AHEAD])
+            .unwrap();
+        assert_eq!(&*state.encode_prompt(b">"), b">");
+
+        state.write(&[IAC, IAC_DO, option::END_OF_RECORD]).unwrap();
+        assert_eq!(&*state.encode_prompt(b">"), &[b'>', IAC, IAC_END_OF_RECORD]);
+    }
+
+    #[test]
+    fn answers_every_timing_mark() {
+        let mut state = State::new(&StateConfig::default());
+
+        for _ in 0..2 {
+            let result = state.write(&[IAC, IAC_DO, option::TIMING_MARK]).unwrap();
+            assert_eq!(
+                result.as_deref(),
+                Some(&[IAC, IAC_WILL, option::TIMING_MARK][..])
+            );
+        }
+
+        assert!(!state.is_local_enabled(option::TIMING_MARK));
+    }
+
+    #[test]
+    fn counts_answered_timing_marks() {
+        let mut state = State::new(&StateConfig::default());
+
+        /* Answers that haven't been requested don't count and are refused */
+        let result = state.write(&[IAC, IAC_WILL, option::TIMING_MARK]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[IAC, IAC_DONT, option::TIMING_MARK][..])
+        );
+        assert_eq!(state.timing_marks_answered(), 0);
+
+        let (_, first) = state.request_timing_mark();
+        let (_, second) = state.request_timing_mark();
+
+        let result = state.write(&[IAC, IAC_WONT, option::TIMING_MARK]).unwrap();
+        assert!(result.is_none());
+        assert_eq!(state.timing_marks_answered(), first);
+
+        let result = state.write(&[IAC, IAC_WILL, option::TIMING_MARK]).unwrap();
+        assert!(result.is_none());
+        assert_eq!(state.timing_marks_answered(), second);
+    }
+
+    #[test]
+    fn erases_whole_utf8_characters() {
+        let mut state = State::new(&StateConfig::default());
+
+        state.write("aä😀".as_bytes()).unwrap();
+        state.write(&[CHAR_DELETE, CHAR_BACK_SPACE]).unwrap();
+        state.write("ö\r\n".as_bytes()).unwrap();
+
+        let mut buffer = [0; 16];
+        let length = state.read_data(&mut buffer);
+        assert_eq!(&buffer[..length], "aö\n".as_bytes());
+    }
+
+    #[test]
+    fn negotiates_charset() {
+        let mut state = State::new(&StateConfig::default());
+
+        let mut expected = vec![IAC, IAC_DO, option::CHARSET];
+        expected.extend([IAC, IAC_SUBNEGOTIATION_START, option::CHARSET]);
+        expected.extend(b"\x01;UTF-8;ISO-8859-1;IBM437");
+        expected.extend([IAC, IAC_SUBNEGOTIATION_END]);
+
+        let result = state.write(&[IAC, IAC_WILL, option::CHARSET]).unwrap();
+        assert_eq!(result.as_deref(), Some(&expected[..]));
+
+        /* Enabling the other side must not result in another request */
+        let result = state.write(&[IAC, IAC_DO, option::CHARSET]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[IAC, IAC_WILL, option::CHARSET][..])
+        );
+
+        state
+            .write(&[IAC, IAC_SUBNEGOTIATION_START, option::CHARSET, 2])
+            .unwrap();
+        state.write(b"utf-8").unwrap();
+        state.write(&[IAC, IAC_SUBNEGOTIATION_END]).unwrap();
+
+        assert_eq!(state.charset(), Some("UTF-8"));
+        assert_eq!(
+            state.poll_event(),
+            Some(Event::Charset("UTF-8".to_string()))
+        );
+    }
+
+    #[test]
+    fn selects_codec_from_terminal_type() {
+        let mut state = State::new(&StateConfig::default());
+        state
+            .write(&[IAC, IAC_WILL, option::TERMINAL_TYPE])
+            .unwrap();
+
+        for terminal_type in [&b"SYNCTERM"[..], b"SYNCTERM"] {
+            state
+                .write(&[IAC, IAC_SUBNEGOTIATION_START, option::TERMINAL_TYPE, 0])
+                .unwrap();
+            state.write(terminal_type).unwrap();
+            state.write(&[IAC, IAC_SUBNEGOTIATION_END]).unwrap();
+        }
+
+        assert_eq!(state.codec().name(), "IBM437");
+
+        /* Every byte is a character of its own */
+        state.write(&[b'a', 0x84, 0x94, CHAR_DELETE]).unwrap();
+
+        let mut buffer = [0; 16];
+        let length = state.read_data(&mut buffer);
+        assert_eq!(state.codec().decode(&buffer[..length]), "aä");
+    }
+
+    #[test]
+    fn keeps_configured_codec() {
+        let mut state = State::new(&StateConfig {
+            codec: Some(Arc::new(codec::Latin1)),
+            ..Default::default()
+        });
+
+        state.write(&[IAC, IAC_WILL, option::CHARSET]).unwrap();
+        state
+            .write(&[IAC, IAC_SUBNEGOTIATION_START, option::CHARSET, 2])
+            .unwrap();
+        state.write(b"UTF-8").unwrap();
+        state.write(&[IAC, IAC_SUBNEGOTIATION_END]).unwrap();
+
+        assert_eq!(state.charset(), Some("UTF-8"));
+        assert_eq!(state.codec().name(), "ISO-8859-1");
+    }
+
+    #[cfg(feature = "mccp")]
+    #[test]
+    fn compresses_output_after_sub_negotiation() {
+        use crate::telnet::compression::Decompressor;
+
+        let mut state = State::new(&StateConfig::default());
+        state.enable_local(option::COMPRESS2);
+
+        let result = state.write(&[IAC, IAC_DO, option::COMPRESS2]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(
+                &[
+                    IAC,
+                    IAC_SUBNEGOTIATION_START,
+                    option::COMPRESS2,
+                    IAC,
+                    IAC_SUBNEGOTIATION_END
+                ][..]
+            )
+        );
+
+        let mut decompressor = Decompressor::new();
+        let encoded = state.encode(b"Hello\n");
+        assert_eq!(
+            decompressor.decompress(&encoded).unwrap(),
+            (b"Hello\r\n".to_vec(), None)
+        );
+
+        /* The answer is the last part of the stream */
+        let result = state
+            .write(&[IAC, IAC_DONT, option::COMPRESS2])
+            .unwrap()
+            .unwrap();
+        let (data, end) = decompressor.decompress(&result).unwrap();
+        assert_eq!(data, [IAC, IAC_WONT, option::COMPRESS2]);
+        assert_eq!(end, Some(result.len()));
+
+        assert_eq!(&*state.encode(b"plain"), b"plain");
+    }
+
+    #[cfg(feature = "mccp")]
+    #[test]
+    fn decompresses_input_after_sub_negotiation() {
+        use crate::telnet::compression::Compressor;
+
+        let mut state = State::new(&StateConfig::default());
+        state.enable_local(option::COMPRESS3);
+        state.write(&[IAC, IAC_DO, option::COMPRESS3]).unwrap();
+
+        let mut compressor = Compressor::new();
+        let mut data = vec![IAC, IAC_SUBNEGOTIATION_START, option::COMPRESS3];
+        data.extend([IAC, IAC_SUBNEGOTIATION_END]);
+        data.extend(compressor.compress(b"compressed\r\n"));
+        data.extend(compressor.finish());
+        data.extend(b"plain\r\n");
+
+        state.write(&data).unwrap();
+
+        let mut buffer = [0; 32];
+        let length = state.read_data(&mut buffer);
+        assert_eq!(&buffer[..length], b"compressed\nplain\n");
+    }
+
+    #[test]
+    fn announces_msdp_variables() {
+        let mut state = State::new(&StateConfig::default());
+        state.write(&[IAC, IAC_DO, option::MSDP]).unwrap();
+
+        state
+            .write(&[IAC, IAC_SUBNEGOTIATION_START, option::MSDP])
+            .unwrap();
+        state.write(b"\x01REPORT\x02HEALTH").unwrap();
+        state.write(&[IAC, IAC_SUBNEGOTIATION_END]).unwrap();
+
+        assert_eq!(
+            state.poll_event(),
+            Some(Event::Msdp(
+                "REPORT".to_string(),
+                MsdpValue::String("HEALTH".to_string())
+            ))
+        );
+    }
+
+    #[cfg(feature = "gmcp")]
+    #[test]
+    fn exchanges_gmcp_messages() {
+        let mut state = State::new(&StateConfig::default());
+
+        let message = GmcpMessage {
+            name: "Char.Vitals".to_string(),
+            data: Some(serde_json::json!({ "hp": 100 })),
+        };
+        assert!(state.encode_gmcp(&message).is_none());
+
+        state.write(&[IAC, IAC_DO, option::GMCP]).unwrap();
+
+        let mut expected = vec![IAC, IAC_SUBNEGOTIATION_START, option::GMCP];
+        expected.extend(br#"Char.Vitals {"hp":100}"#);
+        expected.extend([IAC, IAC_SUBNEGOTIATION_END]);
+        assert_eq!(state.encode_gmcp(&message).as_deref(), Some(&expected[..]));
+
+        state.write(&expected).unwrap();
+        assert_eq!(state.poll_event(), Some(Event::Gmcp(message)));
+    }
+
+    #[test]
+    fn answers_mssp_with_server_status() {
+        let mut state = State::new(&StateConfig {
+            mssp: Some(Arc::new(|| vec![("NAME".to_string(), "MUD".to_string())])),
+            ..Default::default()
+        });
+
+        let mut expected = vec![IAC, IAC_WILL, option::MSSP];
+        expected.extend([IAC, IAC_SUBNEGOTIATION_START, option::MSSP]);
+        expected.extend(b"\x01NAME\x02MUD");
+        expected.extend([IAC, IAC_SUBNEGOTIATION_END]);
+
+        let result = state.write(&[IAC, IAC_DO, option::MSSP]).unwrap();
+        assert_eq!(result.as_deref(), Some(&expected[..]));
+    }
+
+    #[test]
+    fn refuses_mssp_without_provider() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_DO, option::MSSP]).unwrap();
+        assert_eq!(result.as_deref(), Some(&[IAC, IAC_WONT, option::MSSP][..]));
+    }
+
+    #[test]
+    fn closes_once_after_logout() {
+        let mut state = State::new(&StateConfig::default());
+
+        let result = state.write(&[IAC, IAC_DO, option::LOGOUT]).unwrap();
+        assert_eq!(
+            result.as_deref(),
+            Some(&[IAC, IAC_WILL, option::LOGOUT][..])
+        );
+
+        /* A held back CR is flushed before closing */
+        state.encode(b"Bye\r");
+        assert_eq!(state.close().as_deref(), Some(&[b'\r', CHAR_NUL][..]));
+        assert!(state.is_closed());
+        assert_eq!(state.poll_event(), Some(Event::Closed));
+
+        assert_eq!(state.close(), None);
+        assert_eq!(state.poll_event(), None);
+    }
+
+    #[test]
+    fn answers_status_with_enabled_options() {
+        let mut state = State::new(&StateConfig::default());
+
+        state.write(&[IAC, IAC_DO, option::STATUS]).unwrap();
+        state.write(&[IAC, IAC_DO, option::ECHO]).unwrap();
+        state.write(&[IAC, IAC_WILL, option::NAWS]).unwrap();
+
+        let mut request = vec![IAC, IAC_SUBNEGOTIATION_START, option::STATUS];
+        request.extend([status::SEND, IAC, IAC_SUBNEGOTIATION_END]);
+
+        /* IS, followed by the options of both sides */
+        let mut expected = vec![IAC, IAC_SUBNEGOTIATION_START, option::STATUS, 0];
+        expected.extend([IAC_WILL, option::ECHO, IAC_WILL, option::STATUS]);
+        expected.extend([IAC_DO, option::NAWS]);
+        expected.extend([IAC, IAC_SUBNEGOTIATION_END]);
+
+        let result = state.write(&request).unwrap();
+        assert_eq!(result.as_deref(), Some(&expected[..]));
+        assert_eq!(
+            state.enabled_options(Side::Local),
+            [option::ECHO, option::STATUS]
+        );
+    }
+
+    #[test]
+    fn announces_status_of_client() {
+        let mut state = State::new(&StateConfig::default());
+        assert_eq!(state.request_status(), None);
+
+        state.write(&[IAC, IAC_WILL, option::STATUS]).unwrap();
+
+        let mut request = vec![IAC, IAC_SUBNEGOTIATION_START, option::STATUS];
+        request.extend([status::SEND, IAC, IAC_SUBNEGOTIATION_END]);
+        assert_eq!(state.request_status().as_deref(), Some(&request[..]));
+
+        let mut answer = vec![IAC, IAC_SUBNEGOTIATION_START, option::STATUS, 0];
+        answer.extend([IAC_WILL, option::STATUS, IAC_DO, option::ECHO]);
+        answer.extend([IAC, IAC_SUBNEGOTIATION_END]);
+        state.write(&answer).unwrap();
+
+        assert_eq!(
+            state.poll_event(),
+            Some(Event::Status(status::StatusReport {
+                local: vec![option::ECHO],
+                remote: vec![option::STATUS],
+            }))
+        );
     }
 }
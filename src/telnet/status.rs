@@ -0,0 +1,144 @@
+//! STATUS option, see [RFC-859](https://www.rfc-editor.org/rfc/rfc859)
+//!
+//! Lets either side ask for the other side's view of the enabled options,
+//! which helps to debug negotiation mismatches. The view of the client is
+//! announced as [`StatusReport`].
+
+use alloc::{vec, vec::Vec};
+
+/// Subnegotiation command: the sender tells its view of the options
+const IS: u8 = 0;
+/// Subnegotiation command: the sender asks for the view of the options
+pub(crate) const SEND: u8 = 1;
+
+/* These are the TELNET command codes, but used within the subnegotiation
+ * without a preceding IAC. */
+const SE: u8 = 240;
+const SB: u8 = 250;
+const WILL: u8 = 251;
+const WONT: u8 = 252;
+const DO: u8 = 253;
+const DONT: u8 = 254;
+
+/// View of the client on the enabled options
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatusReport {
+    /// Options that the client believes to be enabled on our side
+    pub local: Vec<u8>,
+    /// Options that the client believes to be enabled on its side
+    pub remote: Vec<u8>,
+}
+
+/// Builds the payload that tells our view of the enabled options
+///
+/// # Arguments
+///
+/// * `local` - Options enabled on our side
+/// * `remote` - Options enabled on the other side
+pub(crate) fn encode(local: &[u8], remote: &[u8]) -> Vec<u8> {
+    let mut payload = vec![IS];
+
+    let commands = local
+        .iter()
+        .map(|&option| (WILL, option))
+        .chain(remote.iter().map(|&option| (DO, option)));
+
+    for (command, option) in commands {
+        payload.extend([command, option]);
+
+        /* A literal SE has to be doubled within the list */
+        if option == SE {
+            payload.push(SE);
+        }
+    }
+
+    payload
+}
+
+/// Parses the view of the other side
+///
+/// # Arguments
+///
+/// * `data` - Un-escaped payload of the subnegotiation, without the option
+///   code
+///
+/// # Returns
+///
+/// * `Some(StatusReport)` if the payload is a STATUS IS
+/// * `None` otherwise
+pub(crate) fn parse(data: &[u8]) -> Option<StatusReport> {
+    let list = match data {
+        [IS, list @ ..] => list,
+        _ => return None,
+    };
+
+    let mut report = StatusReport::default();
+    let mut bytes = list.iter().copied().peekable();
+
+    while let Some(command) = bytes.next() {
+        match command {
+            WILL | WONT | DO | DONT => {
+                let option = match bytes.next() {
+                    Some(o) => o,
+                    None => break,
+                };
+
+                if option == SE {
+                    bytes.next_if_eq(&SE);
+                }
+
+                /* The other side performs what it WILLs and expects us to
+                 * perform what it DOs */
+                match command {
+                    WILL => report.remote.push(option),
+                    DO => report.local.push(option),
+                    _ => {}
+                }
+            }
+            SB => {
+                /* Current subnegotiation state, which ends at a single SE */
+                while let Some(byte) = bytes.next() {
+                    if byte == SE && bytes.next_if_eq(&SE).is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_enabled_options() {
+        assert_eq!(
+            encode(&[1, 3], &[31, SE]),
+            [IS, WILL, 1, WILL, 3, DO, 31, DO, SE, SE]
+        );
+    }
+
+    #[test]
+    fn parses_view_of_other_side() {
+        let data = [
+            IS, WILL, 3, DONT, 1, SB, 31, 0, 80, SE, SE, 0, 24, SE, DO, SE, SE, WILL, 24,
+        ];
+
+        assert_eq!(
+            parse(&data),
+            Some(StatusReport {
+                local: vec![SE],
+                remote: vec![3, 24],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_requests() {
+        assert_eq!(parse(&[SEND]), None);
+    }
+}
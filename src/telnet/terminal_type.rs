@@ -0,0 +1,203 @@
+//! TERMINAL-TYPE option, see [RFC-1091](https://www.rfc-editor.org/rfc/rfc1091)
+//!
+//! Lets the client tell us which terminal types it supports. As the client
+//! only sends one type per request, we keep asking until it repeats itself.
+//! MUD clients additionally announce their capabilities via
+//! [MTTS](https://tintin.mudhalla.net/protocols/mtts/), which can be parsed
+//! with [`mtts`].
+
+use alloc::{string::String, vec, vec::Vec};
+
+/// Subnegotiation command: the sender tells its terminal type
+const IS: u8 = 0;
+/// Subnegotiation command: the sender asks for the terminal type
+const SEND: u8 = 1;
+
+/// Maximum number of terminal types that are requested, in case a client never
+/// repeats itself
+const MAX_TERMINAL_TYPES: usize = 16;
+
+/// Prefix of an MTTS capability announcement
+const MTTS_PREFIX: &str = "MTTS ";
+
+/// MTTS flag: client supports all common ANSI color codes
+pub const MTTS_ANSI: u32 = 1;
+/// MTTS flag: client supports all common VT100 codes
+pub const MTTS_VT100: u32 = 2;
+/// MTTS flag: client is using UTF-8 character encoding
+pub const MTTS_UTF8: u32 = 4;
+/// MTTS flag: client supports all 256 color codes
+pub const MTTS_256_COLORS: u32 = 8;
+/// MTTS flag: client supports xterm mouse tracking
+pub const MTTS_MOUSE_TRACKING: u32 = 16;
+/// MTTS flag: client supports the OSC color palette
+pub const MTTS_OSC_COLOR_PALETTE: u32 = 32;
+/// MTTS flag: client is using a screen reader
+pub const MTTS_SCREEN_READER: u32 = 64;
+/// MTTS flag: client is a proxy allowing different users to connect from the
+/// same IP address
+pub const MTTS_PROXY: u32 = 128;
+/// MTTS flag: client supports truecolor codes
+pub const MTTS_TRUECOLOR: u32 = 256;
+/// MTTS flag: client supports the Mud New Environment Standard
+pub const MTTS_MNES: u32 = 512;
+/// MTTS flag: client supports the Mud Server Link Protocol
+pub const MTTS_MSLP: u32 = 1024;
+/// MTTS flag: client supports SSL for data encryption
+pub const MTTS_SSL: u32 = 2048;
+
+/// Returns the MTTS capability flags out of given terminal types, if the
+/// client has announced any
+///
+/// # Arguments
+///
+/// * `terminal_types` - All terminal types the client has sent
+///
+/// # Examples
+///
+/// ```rust
+/// use telnet_server::telnet::terminal_type::{mtts, MTTS_ANSI, MTTS_UTF8};
+///
+/// let terminal_types = ["MUDLET".to_string(), "XTERM".to_string(), "MTTS 5".to_string()];
+/// assert_eq!(mtts(&terminal_types), Some(MTTS_ANSI | MTTS_UTF8));
+///
+/// assert_eq!(mtts(&["XTERM".to_string()]), None);
+/// ```
+pub fn mtts(terminal_types: &[String]) -> Option<u32> {
+    terminal_types.iter().find_map(|t| {
+        t.strip_prefix(MTTS_PREFIX)
+            .and_then(|flags| flags.trim().parse().ok())
+    })
+}
+
+/// Server side state of the TERMINAL-TYPE option
+#[derive(Default)]
+pub(crate) struct TerminalType {
+    /// Every terminal type the client has sent, in order
+    terminal_types: Vec<String>,
+    /// Whether the client has sent all of its terminal types
+    is_complete: bool,
+}
+
+impl TerminalType {
+    /// Has to be called once the client has enabled TERMINAL-TYPE
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn enable(&mut self) -> Vec<Vec<u8>> {
+        vec![vec![SEND]]
+    }
+
+    /// Has to be called once the client has disabled TERMINAL-TYPE
+    pub(crate) fn disable(&mut self) {
+        self.terminal_types.clear();
+        self.is_complete = false;
+    }
+
+    /// Handles an incoming TERMINAL-TYPE subnegotiation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Un-escaped payload of the subnegotiation, without the option
+    ///   code
+    ///
+    /// # Returns
+    ///
+    /// Subnegotiation payloads that have to be sent to the client
+    pub(crate) fn handle(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let name = match data {
+            [IS, name @ ..] => String::from_utf8_lossy(name).into_owned(),
+            _ => return vec![],
+        };
+
+        if self.is_complete {
+            /* Answer to a request that hasn't been sent by us */
+            return vec![];
+        }
+
+        /* A client signals the end of its list by repeating the last type.
+         * Some clients start over instead, so a repeated first type also
+         * marks the end. */
+        if self.terminal_types.last() == Some(&name) || self.terminal_types.first() == Some(&name) {
+            self.is_complete = true;
+            return vec![];
+        }
+
+        self.terminal_types.push(name);
+
+        if self.terminal_types.len() >= MAX_TERMINAL_TYPES {
+            self.is_complete = true;
+            return vec![];
+        }
+
+        vec![vec![SEND]]
+    }
+
+    /// Returns every terminal type the client has sent so far
+    pub(crate) fn terminal_types(&self) -> &[String] {
+        &self.terminal_types
+    }
+
+    /// Returns whether the client has sent all of its terminal types
+    pub(crate) fn is_complete(&self) -> bool {
+        self.is_complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is(name: &str) -> Vec<u8> {
+        let mut data = vec![IS];
+        data.extend_from_slice(name.as_bytes());
+        data
+    }
+
+    #[test]
+    fn collects_until_repeated() {
+        let mut terminal_type = TerminalType::default();
+        assert_eq!(terminal_type.enable(), vec![vec![SEND]]);
+
+        assert_eq!(terminal_type.handle(&is("MUDLET")), vec![vec![SEND]]);
+        assert_eq!(
+            terminal_type.handle(&is("XTERM-256COLOR")),
+            vec![vec![SEND]]
+        );
+        assert_eq!(terminal_type.handle(&is("MTTS 2825")), vec![vec![SEND]]);
+        assert!(terminal_type.handle(&is("MTTS 2825")).is_empty());
+
+        assert!(terminal_type.is_complete());
+        assert_eq!(
+            terminal_type.terminal_types(),
+            ["MUDLET", "XTERM-256COLOR", "MTTS 2825"]
+        );
+        assert_eq!(mtts(terminal_type.terminal_types()), Some(2825));
+    }
+
+    #[test]
+    fn stops_when_cycled() {
+        let mut terminal_type = TerminalType::default();
+
+        terminal_type.handle(&is("XTERM"));
+        terminal_type.handle(&is("VT100"));
+        assert!(terminal_type.handle(&is("XTERM")).is_empty());
+
+        assert!(terminal_type.is_complete());
+        assert_eq!(terminal_type.terminal_types(), ["XTERM", "VT100"]);
+    }
+
+    #[test]
+    fn stops_at_limit() {
+        let mut terminal_type = TerminalType::default();
+
+        for i in 0..MAX_TERMINAL_TYPES {
+            terminal_type.handle(&is(&i.to_string()));
+        }
+
+        assert!(terminal_type.is_complete());
+        assert!(terminal_type.handle(&is("ANOTHER")).is_empty());
+        assert_eq!(terminal_type.terminal_types().len(), MAX_TERMINAL_TYPES);
+    }
+}